@@ -0,0 +1,263 @@
+use crate::expr::{Expr, ExprId, ExprVisitor};
+use crate::stmt::{Stmt, StmtVisitor};
+use crate::token::{Literal, Token};
+
+// Renders parsed statements as a Graphviz digraph, one node per Stmt and Expr.
+// Edges point from parent to child and are labeled with the child's role.
+pub fn to_dot(statements: &[Stmt]) -> String {
+    let mut printer = DotPrinter::default();
+    for statement in statements {
+        statement.accept(&mut printer);
+    }
+    format!("digraph ast {{\n{}}}\n", printer.out)
+}
+
+#[derive(Default)]
+struct DotPrinter {
+    out: String,
+    nodes: usize,
+}
+
+impl DotPrinter {
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.out.push_str(format!("  n{} [label=\"{}\"];\n", id, label).as_str());
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, role: &str) {
+        self.out.push_str(format!("  n{} -> n{} [label=\"{}\"];\n", from, to, role).as_str());
+    }
+
+    fn expr(&mut self, parent: usize, expr: &Expr, role: &str) {
+        let child = expr.accept(self);
+        self.edge(parent, child, role);
+    }
+
+    fn stmt(&mut self, parent: usize, stmt: &Stmt, role: &str) {
+        let child = stmt.accept(self);
+        self.edge(parent, child, role);
+    }
+
+    // Statement lists get numbered edges so their order stays visible
+    fn stmts(&mut self, parent: usize, statements: &[Stmt]) {
+        for (i, statement) in statements.iter().enumerate() {
+            self.stmt(parent, statement, i.to_string().as_str());
+        }
+    }
+}
+
+fn names(params: &[Token]) -> String {
+    let names: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+    names.join(", ")
+}
+
+impl ExprVisitor<usize> for DotPrinter {
+    fn visit_literal(&mut self, literal: &Literal, token: &Token) -> usize {
+        match literal {
+            Literal::Number(_) => self.node(token.lexeme.as_str()),
+            Literal::String(s) => self.node(format!("\"{}\"", s).as_str()),
+            _ => self.node(literal.to_string().as_str()),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> usize {
+        let id = self.node(operator.lexeme.as_str());
+        self.expr(id, right, "right");
+        id
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> usize {
+        let id = self.node(operator.lexeme.as_str());
+        self.expr(id, left, "left");
+        self.expr(id, right, "right");
+        id
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> usize {
+        let id = self.node("call");
+        self.expr(id, callee, "callee");
+        for (i, argument) in arguments.iter().enumerate() {
+            self.expr(id, argument, format!("arg {}", i).as_str());
+        }
+        id
+    }
+
+    fn visit_grouping(&mut self, _paren: &Token, expr: &Expr) -> usize {
+        let id = self.node("( )");
+        self.expr(id, expr, "expr");
+        id
+    }
+
+    fn visit_variable(&mut self, name: &Token, _id: ExprId) -> usize {
+        self.node(name.lexeme.as_str())
+    }
+
+    fn visit_assign(&mut self, name: &Token, value: &Expr, _id: ExprId) -> usize {
+        let id = self.node(format!("{} =", name.lexeme).as_str());
+        self.expr(id, value, "value");
+        id
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> usize {
+        let id = self.node(operator.lexeme.as_str());
+        self.expr(id, left, "left");
+        self.expr(id, right, "right");
+        id
+    }
+
+    fn visit_ternary(&mut self, condition: &Expr, _question: &Token, then: &Expr, otherwise: &Expr) -> usize {
+        let id = self.node("? :");
+        self.expr(id, condition, "condition");
+        self.expr(id, then, "then");
+        self.expr(id, otherwise, "else");
+        id
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) -> usize {
+        let id = self.node(format!(".{}", name.lexeme).as_str());
+        self.expr(id, object, "object");
+        id
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> usize {
+        let id = self.node(format!(".{} =", name.lexeme).as_str());
+        self.expr(id, object, "object");
+        self.expr(id, value, "value");
+        id
+    }
+
+    fn visit_this(&mut self, _keyword: &Token, _id: ExprId) -> usize {
+        self.node("this")
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token, _id: ExprId) -> usize {
+        self.node(format!("super.{}", method.lexeme).as_str())
+    }
+
+    fn visit_lambda(&mut self, _keyword: &Token, params: &[Token], body: &[Stmt]) -> usize {
+        let id = self.node(format!("fun ({})", names(params)).as_str());
+        self.stmts(id, body);
+        id
+    }
+
+    fn visit_array(&mut self, _bracket: &Token, elements: &[Expr]) -> usize {
+        let id = self.node("[ ]");
+        for (i, element) in elements.iter().enumerate() {
+            self.expr(id, element, i.to_string().as_str());
+        }
+        id
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> usize {
+        let id = self.node("{ }");
+        for (i, (key, value)) in entries.iter().enumerate() {
+            self.expr(id, key, format!("key {}", i).as_str());
+            self.expr(id, value, format!("value {}", i).as_str());
+        }
+        id
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> usize {
+        let id = self.node("[ ]");
+        self.expr(id, object, "object");
+        self.expr(id, index, "index");
+        id
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> usize {
+        let id = self.node("[ ] =");
+        self.expr(id, object, "object");
+        self.expr(id, index, "index");
+        self.expr(id, value, "value");
+        id
+    }
+}
+
+impl StmtVisitor<usize> for DotPrinter {
+    fn visit_var_declaration(&mut self, name: &Token, initializer: Option<&Expr>, constant: bool) -> usize {
+        let id = self.node(format!("{} {}", if constant { "const" } else { "var" }, name.lexeme).as_str());
+        if let Some(e) = initializer {
+            self.expr(id, e, "initializer");
+        }
+        id
+    }
+
+    fn visit_print(&mut self, expr: &Expr) -> usize {
+        let id = self.node("print");
+        self.expr(id, expr, "expr");
+        id
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) -> usize {
+        let id = self.node("expression");
+        self.expr(id, expr, "expr");
+        id
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> usize {
+        let id = self.node("block");
+        self.stmts(id, statements);
+        id
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> usize {
+        let id = self.node("if");
+        self.expr(id, condition, "condition");
+        self.stmt(id, then_branch, "then");
+        if let Some(e) = else_branch {
+            self.stmt(id, e, "else");
+        }
+        id
+    }
+
+    fn visit_while(&mut self, keyword: &Token, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> usize {
+        let id = self.node(keyword.lexeme.as_str());
+        self.expr(id, condition, "condition");
+        self.stmt(id, body, "body");
+        if let Some(i) = increment {
+            self.expr(id, i, "increment");
+        }
+        id
+    }
+
+    fn visit_for_each(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> usize {
+        let id = self.node(format!("for {} in", name.lexeme).as_str());
+        self.expr(id, iterable, "iterable");
+        self.stmt(id, body, "body");
+        id
+    }
+
+    fn visit_break(&mut self) -> usize {
+        self.node("break")
+    }
+
+    fn visit_continue(&mut self) -> usize {
+        self.node("continue")
+    }
+
+    fn visit_function(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> usize {
+        let id = self.node(format!("fun {}({})", name.lexeme, names(params)).as_str());
+        self.stmts(id, body);
+        id
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> usize {
+        let id = self.node("return");
+        if let Some(e) = value {
+            self.expr(id, e, "value");
+        }
+        id
+    }
+
+    fn visit_class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Stmt]) -> usize {
+        let id = self.node(format!("class {}", name.lexeme).as_str());
+        if let Some(s) = superclass {
+            self.expr(id, s, "superclass");
+        }
+        self.stmts(id, methods);
+        id
+    }
+}
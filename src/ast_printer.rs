@@ -1 +1,164 @@
-// Implemented as Display for Expr
\ No newline at end of file
+use crate::expr::{Expr, ExprId, ExprVisitor};
+use crate::stmt::{Stmt, StmtVisitor};
+use crate::token::{Literal, Token};
+
+// Renders the tree through the visitor traits, producing the same text as the Display impls
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print(&mut self, statement: &Stmt) -> String {
+        statement.accept(self)
+    }
+}
+
+impl ExprVisitor<String> for AstPrinter {
+    fn visit_literal(&mut self, literal: &Literal, token: &Token) -> String {
+        match literal {
+            Literal::Number(_) => token.lexeme.clone(),
+            _ => literal.to_string(),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> String {
+        format!("({}{})", operator.lexeme, right.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        format!("({} {} {})", left.accept(self), operator.lexeme, right.accept(self))
+    }
+
+    fn visit_call(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) -> String {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.accept(self)).collect();
+        format!("({}({}))", callee.accept(self), arguments.join(", "))
+    }
+
+    fn visit_grouping(&mut self, _paren: &Token, expr: &Expr) -> String {
+        format!("({})", expr.accept(self))
+    }
+
+    fn visit_variable(&mut self, name: &Token, _id: ExprId) -> String {
+        format!("(variable: {})", name.lexeme)
+    }
+
+    fn visit_assign(&mut self, name: &Token, value: &Expr, _id: ExprId) -> String {
+        format!("({} {} {})", name, name, value.accept(self))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> String {
+        format!("({} {} {})", left.accept(self), operator.lexeme, right.accept(self))
+    }
+
+    fn visit_ternary(&mut self, condition: &Expr, _question: &Token, then: &Expr, otherwise: &Expr) -> String {
+        format!("({} ? {} : {})", condition.accept(self), then.accept(self), otherwise.accept(self))
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Token) -> String {
+        format!("({}.{})", object.accept(self), name.lexeme)
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> String {
+        format!("({}.{} = {})", object.accept(self), name.lexeme, value.accept(self))
+    }
+
+    fn visit_this(&mut self, _keyword: &Token, _id: ExprId) -> String {
+        String::from("this")
+    }
+
+    fn visit_super(&mut self, _keyword: &Token, method: &Token, _id: ExprId) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_lambda(&mut self, _keyword: &Token, params: &[Token], _body: &[Stmt]) -> String {
+        let names: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+        format!("(fun ({}))", names.join(", "))
+    }
+
+    fn visit_array(&mut self, _bracket: &Token, elements: &[Expr]) -> String {
+        let elements: Vec<String> = elements.iter().map(|e| e.accept(self)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn visit_map(&mut self, _brace: &Token, entries: &[(Expr, Expr)]) -> String {
+        let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k.accept(self), v.accept(self))).collect();
+        format!("{{{}}}", entries.join(", "))
+    }
+
+    fn visit_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr) -> String {
+        format!("({}[{}])", object.accept(self), index.accept(self))
+    }
+
+    fn visit_set_index(&mut self, object: &Expr, _bracket: &Token, index: &Expr, value: &Expr) -> String {
+        format!("({}[{}] = {})", object.accept(self), index.accept(self), value.accept(self))
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_var_declaration(&mut self, name: &Token, initializer: Option<&Expr>, constant: bool) -> String {
+        let kind = if constant { "ConstDeclaration" } else { "VarDeclaration" };
+        match initializer {
+            Some(e) => format!("{} {}= {}", kind, name, e.accept(self)),
+            None => format!("{} {}", kind, name),
+        }
+    }
+
+    fn visit_print(&mut self, expr: &Expr) -> String {
+        format!("Print {}", expr.accept(self))
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) -> String {
+        format!("Expr {}", expr.accept(self))
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        let mut ret = String::from("Block: \n");
+        for s in statements {
+            ret.push_str(format!("\t{}\n", s.accept(self)).as_str());
+        }
+        ret
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> String {
+        let ret = format!("If {} {}", condition.accept(self), then_branch.accept(self));
+        match else_branch {
+            Some(e) => format!("{}= {}", ret, e.accept(self)),
+            None => ret,
+        }
+    }
+
+    fn visit_while(&mut self, _keyword: &Token, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> String {
+        match increment {
+            Some(i) => format!("While [{}] [{}] [{}]", condition.accept(self), body.accept(self), i.accept(self)),
+            None => format!("While [{}] [{}]", condition.accept(self), body.accept(self)),
+        }
+    }
+
+    fn visit_for_each(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> String {
+        format!("ForEach {} in [{}] [{}]", name.lexeme, iterable.accept(self), body.accept(self))
+    }
+
+    fn visit_break(&mut self) -> String {
+        String::from("Break")
+    }
+
+    fn visit_continue(&mut self) -> String {
+        String::from("Continue")
+    }
+
+    fn visit_function(&mut self, name: &Token, _params: &[Token], _body: &[Stmt]) -> String {
+        format!("fun {}", name.lexeme)
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: Option<&Expr>) -> String {
+        match value {
+            Some(e) => format!("Return {}", e.accept(self)),
+            None => String::from("Return"),
+        }
+    }
+
+    fn visit_class(&mut self, name: &Token, superclass: Option<&Expr>, _methods: &[Stmt]) -> String {
+        match superclass {
+            Some(Expr::Variable(s, _)) => format!("class {} < {}", name.lexeme, s.lexeme),
+            _ => format!("class {}", name.lexeme),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::token::{Literal, Token, TokenType};
+use crate::vm::{Chunk, OpCode, SrcLocation, VmValue};
+
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at line: {}:{}.", self.message, self.line, self.col)
+    }
+}
+
+// Translates the parsed program into bytecode for the VM. Only literals, arithmetic, global
+// variables and control flow are supported so far. Every top-level statement gets its own chunk ending in OpReturn.
+// The results of expression statements are discarded with OpPop.
+pub struct Compiler {
+    chunk: Chunk,
+    // Position of the token being compiled, attached to the emitted bytes
+    location: SrcLocation,
+    // Blocks enclosing the statement being compiled
+    scope_depth: usize,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            location: SrcLocation{line: 1, col: 1},
+            scope_depth: 0,
+        }
+    }
+}
+
+impl Compiler {
+    pub fn compile(&mut self, statements: &Vec<Stmt>) -> Result<Vec<Chunk>, CompileError> {
+        let mut chunks = vec![];
+        for statement in statements {
+            self.statement(statement)?;
+            self.emit(OpCode::OpReturn);
+            chunks.push(std::mem::take(&mut self.chunk));
+        }
+        Ok(chunks)
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CompileError> {
+        match statement {
+            Stmt::Print(e) => {
+                self.expression(e)?;
+                self.emit(OpCode::OpPrint);
+                Ok(())
+            }
+            Stmt::Expression(e) => {
+                self.expression(e)?;
+                self.emit(OpCode::OpPop);
+                Ok(())
+            }
+            Stmt::VarDeclaration(name, _, true) => {
+                self.set_location(name);
+                Err(self.error(String::from("Constants are not supported by the VM yet")))
+            }
+            // Without locals every variable would end up global, unlike in the tree-walker
+            Stmt::VarDeclaration(name, _, false) if self.scope_depth > 0 => {
+                self.set_location(name);
+                Err(self.error(String::from("Local variables are not supported by the VM yet")))
+            }
+            Stmt::VarDeclaration(name, initializer, false) => {
+                match initializer {
+                    Some(e) => self.expression(e)?,
+                    // The VM has no uninitialized state, so reading the variable gives nil where the
+                    // tree-walker reports UninitializedVariable
+                    None => {
+                        self.set_location(name);
+                        self.emit(OpCode::OpNil);
+                    }
+                }
+                self.set_location(name);
+                self.emit_name(OpCode::OpDefineGlobal, name)
+            }
+            Stmt::Block(statements) => {
+                self.scope_depth += 1;
+                let result = statements.iter().try_for_each(|s| self.statement(s));
+                self.scope_depth -= 1;
+                result
+            }
+            // There are no comparison or `!` opcodes yet, so if and while conditions can only test
+            // whether a value is truthy
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition)?;
+                self.set_location(condition.token());
+                let then_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+                self.statement(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::OpJump);
+                self.patch_jump(then_jump)?;
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)
+            }
+            Stmt::While(keyword, condition, body, increment) => {
+                if increment.is_some() {
+                    self.set_location(keyword);
+                    return Err(self.error(String::from("For loop increments are not supported by the VM yet")));
+                }
+                let loop_start = self.chunk.count();
+                self.expression(condition)?;
+                self.set_location(keyword);
+                let exit_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+                self.statement(body)?;
+                self.set_location(keyword);
+                self.emit_loop(loop_start)?;
+                self.patch_jump(exit_jump)
+            }
+            _ => Err(self.error(format!("Statement '{}' is not supported by the VM yet", statement))),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match expr {
+            Expr::Literal(literal, token) => {
+                self.set_location(token);
+                match literal {
+                    Literal::Number(n) => self.emit_constant(VmValue::Double(*n)),
+                    Literal::String(s) => self.emit_constant(VmValue::Obj(s.to_string())),
+                    Literal::True => self.emit(OpCode::OpTrue),
+                    Literal::False => self.emit(OpCode::OpFalse),
+                    Literal::Null => self.emit(OpCode::OpNil),
+                    _ => return Err(self.error(format!("Literal '{}' is not supported by the VM yet", literal))),
+                }
+            }
+            Expr::Grouping(_, e) => self.expression(e)?,
+            Expr::Variable(name, _) => {
+                self.set_location(name);
+                self.emit_name(OpCode::OpGetGlobal, name)?;
+            }
+            Expr::Assign(name, value, _) => {
+                self.expression(value)?;
+                self.set_location(name);
+                self.emit_name(OpCode::OpSetGlobal, name)?;
+            }
+            Expr::Unary(operator, e) => {
+                self.expression(e)?;
+                self.set_location(operator);
+                match operator.token_type {
+                    TokenType::Minus => self.emit(OpCode::OpNegate),
+                    _ => return Err(self.error(format!("Unary '{}' is not supported by the VM yet", operator.lexeme))),
+                }
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.set_location(operator);
+                match operator.token_type {
+                    TokenType::Plus => self.emit(OpCode::OpAdd),
+                    TokenType::Minus => self.emit(OpCode::OpSubtract),
+                    TokenType::Star => self.emit(OpCode::OpMultiply),
+                    TokenType::Slash => self.emit(OpCode::OpDivide),
+                    TokenType::Percent => self.emit(OpCode::OpModulo),
+                    TokenType::StarStar => self.emit(OpCode::OpPower),
+                    _ => return Err(self.error(format!("Operator '{}' is not supported by the VM yet", operator.lexeme))),
+                }
+            }
+            _ => {
+                self.set_location(expr.token());
+                return Err(self.error(format!("Expression '{}' is not supported by the VM yet", expr)));
+            }
+        }
+        Ok(())
+    }
+
+    // The global opcodes take the variable name as a one byte constant index
+    fn emit_name(&mut self, op: OpCode, name: &Token) -> Result<(), CompileError> {
+        let constant = self.chunk.add_constant(VmValue::Obj(name.lexeme.clone()));
+        if constant > u8::MAX as usize {
+            return Err(self.error(String::from("Too many constants in one chunk")));
+        }
+        self.emit(op);
+        self.emit_byte(constant as u8);
+        Ok(())
+    }
+
+    // Emits a forward jump with a placeholder distance, returns where the distance goes
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.chunk.count() - 2
+    }
+
+    // Jumps to the end of the code written so far
+    fn patch_jump(&mut self, offset: usize) -> Result<(), CompileError> {
+        let jump = self.chunk.count() - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err(self.error(String::from("Too much code to jump over")));
+        }
+        self.chunk.patch(offset, (jump >> 8) as u8);
+        self.chunk.patch(offset + 1, jump as u8);
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), CompileError> {
+        self.emit(OpCode::OpLoop);
+        let offset = self.chunk.count() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            return Err(self.error(String::from("Loop body too large")));
+        }
+        self.emit_byte((offset >> 8) as u8);
+        self.emit_byte(offset as u8);
+        Ok(())
+    }
+
+    fn set_location(&mut self, token: &Token) {
+        self.location = SrcLocation{line: token.line, col: token.col};
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_constant(&mut self, value: VmValue) {
+        self.chunk.write_constant(value, self.location.clone());
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.chunk.write_chunk(byte, self.location.clone());
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError{message, line: self.location.line, col: self.location.col}
+    }
+}
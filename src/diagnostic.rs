@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+// A located message meant for tools (editors, CI) rather than humans
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(line: usize, col: usize, message: String) -> Diagnostic {
+        Diagnostic { line, col, severity: Severity::Error, message }
+    }
+
+    pub fn to_json(&self) -> String {
+        format!("{{\"line\":{},\"col\":{},\"severity\":\"{}\",\"message\":\"{}\"}}",
+                self.line, self.col, self.severity, json_escape(&self.message))
+    }
+}
+
+pub fn json_escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(ret, "\\u{:04x}", c as u32).expect(""),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+pub fn to_json_array(diagnostics: &[Diagnostic]) -> String {
+    let items: Vec<String> = diagnostics.iter().map(|d| d.to_json()).collect();
+    format!("[{}]", items.join(","))
+}
+
+// The source line under a gutter with its number, and a caret under the column, like rustc prints it.
+// Tabs before the column are kept so the caret lines up however wide the terminal renders them.
+pub fn snippet(text: &str, line: usize, col: usize) -> String {
+    let gutter = " ".repeat(line.to_string().len());
+    let indent: String = text.chars().take(col.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    format!("{} |\n{} | {}\n{} | {}^", gutter, line, text, gutter, indent)
+}
@@ -1,33 +1,72 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use crate::interpreter::Value;
 use crate::interpreter::RuntimeError;
 
 
-#[derive(Default, Clone)]
+// Scopes are shared: blocks, calls and closures hold references to the same enclosing
+// environments, so assignments are visible to everyone holding the scope
+#[derive(Default)]
 pub struct Environment {
-    enclosing: Option<Box<Environment>>,
-    values: HashMap<String, Option<Value>>
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    values: HashMap<String, Option<Value>>,
+    // Names in values declared with `const`
+    constants: HashSet<String>,
 }
 
 impl Environment {
-    pub fn enclosing(&mut self, enclosing: Option<Box<Environment>>) {
-        self.enclosing = enclosing;
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Rc<RefCell<Environment>> {
+        Rc::new(RefCell::new(Environment{enclosing, values: HashMap::new(), constants: HashSet::new()}))
     }
 
-    pub fn get_enclosing(&mut self) -> Option<Box<Environment>> {
-        self.enclosing.clone()
+    pub fn values(&self) -> &HashMap<String, Option<Value>> {
+        &self.values
     }
 
+    // Renders the scope chain innermost first, one indented level per depth
+    pub fn dump(&self) -> String {
+        let mut ret = String::new();
+        self.dump_into(&mut ret, 0);
+        ret
+    }
+
+    fn dump_into(&self, ret: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        ret.push_str(format!("{}scope {}:\n", indent, depth).as_str());
+        let mut names: Vec<&String> = self.values().keys().collect();
+        names.sort();
+        for name in names {
+            match &self.values()[name] {
+                Some(v) => ret.push_str(format!("{}  {} = {}\n", indent, name, v).as_str()),
+                None => ret.push_str(format!("{}  {} = <uninitialized>\n", indent, name).as_str()),
+            }
+        }
+        if let Some(ref enclosing) = self.enclosing {
+            enclosing.borrow().dump_into(ret, depth + 1);
+        }
+    }
+
+    // Redeclaring a name in the same scope replaces the old binding
     pub fn define(&mut self, name: String, value: Option<Value>) {
-        self.values.entry(name).and_modify(|v| *v = value.clone()).or_insert(value.clone());
+        self.constants.remove(&name);
+        self.values.insert(name, value);
+    }
+
+    pub fn define_constant(&mut self, name: String, value: Option<Value>) {
+        self.constants.insert(name.clone());
+        self.values.insert(name, value);
     }
 
     pub fn assign(&mut self, name: String, value: Value) -> Result<(), RuntimeError>{
+        if self.constants.contains(&name) {
+            return Err(RuntimeError::VariableIsConstant);
+        }
         return match self.values.get_mut(&name) {
             Some(v) => {*v = Some(value); Ok(())},
             None => {
-                if let Some(ref mut enclosing) = self.enclosing {
-                    enclosing.assign(name, value)
+                if let Some(ref enclosing) = self.enclosing {
+                    enclosing.borrow_mut().assign(name, value)
                 } else {
                     Err(RuntimeError::VariableNotFound)
                 }
@@ -35,6 +74,51 @@ impl Environment {
         }
     }
 
+    pub fn get_at(&self, depth: usize, name: String) -> Result<Value, RuntimeError> {
+        if depth == 0 {
+            return match self.values.get(&name) {
+                Some(Some(v)) => Ok(v.clone()),
+                Some(None) => Err(RuntimeError::VariableNotInitialized),
+                None => Err(RuntimeError::VariableNotFound),
+            }
+        }
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow().get_at(depth - 1, name),
+            None => Err(RuntimeError::VariableNotFound),
+        }
+    }
+
+    pub fn assign_at(&mut self, depth: usize, name: String, value: Value) -> Result<(), RuntimeError> {
+        if depth == 0 {
+            if self.constants.contains(&name) {
+                return Err(RuntimeError::VariableIsConstant);
+            }
+            return match self.values.get_mut(&name) {
+                Some(v) => {*v = Some(value); Ok(())},
+                None => Err(RuntimeError::VariableNotFound),
+            }
+        }
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow_mut().assign_at(depth - 1, name, value),
+            None => Err(RuntimeError::VariableNotFound),
+        }
+    }
+
+    // Globals live in the outermost environment of the chain
+    pub fn get_global(&self, name: String) -> Result<Value, RuntimeError> {
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow().get_global(name),
+            None => self.get(name),
+        }
+    }
+
+    pub fn assign_global(&mut self, name: String, value: Value) -> Result<(), RuntimeError> {
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow_mut().assign_global(name, value),
+            None => self.assign(name, value),
+        }
+    }
+
     pub fn get(&self, name: String) -> Result<Value, RuntimeError> {
         match self.values.get(&name) {
             Some(v) => {match v {
@@ -43,7 +127,7 @@ impl Environment {
             }},
             None => {
                 if let Some(ref enclosing) = self.enclosing {
-                    enclosing.get(name)
+                    enclosing.borrow().get(name)
                 } else {
                     Err(RuntimeError::VariableNotFound)
                 }
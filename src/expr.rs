@@ -1,37 +1,162 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::stmt::Stmt;
 use crate::token::{Literal, Token};
 
+// Identifies a variable use so the resolver's scope depths can be looked up by the interpreter.
+// Unique for the whole process, so ids from separate REPL lines never collide.
+pub type ExprId = usize;
+
+static NEXT_EXPR_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub fn next_expr_id() -> ExprId {
+    NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Literal(Literal),
+    Literal(Literal, Token),
     // This,
     Unary(Token, Box<Expr>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Call(Box<Expr>, Token, Vec<Expr>),
-    Grouping(Box<Expr>),
-    Variable(Token), // Get contents of variable
-    Assign(Token, Box<Expr>),  // Assign value to variable
+    Grouping(Token, Box<Expr>),  // Opening parenthesis, inner expression
+    Variable(Token, ExprId), // Get contents of variable
+    Assign(Token, Box<Expr>, ExprId),  // Assign value to variable
     Logical(Box<Expr>, Token, Box<Expr>),
+    Ternary(Box<Expr>, Token, Box<Expr>, Box<Expr>),  // Condition, `?`, then, else
+    Get(Box<Expr>, Token),  // Read a property of an instance
+    Set(Box<Expr>, Token, Box<Expr>),  // Write a property of an instance
+    This(Token, ExprId),
+    Super(Token, Token, ExprId),  // `super` keyword, method name
+    Lambda(Token, Vec<Token>, Vec<Stmt>),  // `fun` keyword, parameters, body
+    Array(Token, Vec<Expr>),  // `[`, elements
+    Map(Token, Vec<(Expr, Expr)>),  // `{`, key and value pairs
+    Index(Box<Expr>, Token, Box<Expr>),  // Indexed value, `[`, index
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),  // Indexed value, `[`, index, assigned value
+}
+
+// One method per Expr variant, so a pass over the tree can't silently miss a variant
+pub trait ExprVisitor<R> {
+    fn visit_literal(&mut self, literal: &Literal, token: &Token) -> R;
+    fn visit_unary(&mut self, operator: &Token, right: &Expr) -> R;
+    fn visit_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> R;
+    fn visit_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> R;
+    fn visit_grouping(&mut self, paren: &Token, expr: &Expr) -> R;
+    fn visit_variable(&mut self, name: &Token, id: ExprId) -> R;
+    fn visit_assign(&mut self, name: &Token, value: &Expr, id: ExprId) -> R;
+    fn visit_logical(&mut self, left: &Expr, operator: &Token, right: &Expr) -> R;
+    fn visit_ternary(&mut self, condition: &Expr, question: &Token, then: &Expr, otherwise: &Expr) -> R;
+    fn visit_get(&mut self, object: &Expr, name: &Token) -> R;
+    fn visit_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> R;
+    fn visit_this(&mut self, keyword: &Token, id: ExprId) -> R;
+    fn visit_super(&mut self, keyword: &Token, method: &Token, id: ExprId) -> R;
+    fn visit_lambda(&mut self, keyword: &Token, params: &[Token], body: &[Stmt]) -> R;
+    fn visit_array(&mut self, bracket: &Token, elements: &[Expr]) -> R;
+    fn visit_map(&mut self, brace: &Token, entries: &[(Expr, Expr)]) -> R;
+    fn visit_index(&mut self, object: &Expr, bracket: &Token, index: &Expr) -> R;
+    fn visit_set_index(&mut self, object: &Expr, bracket: &Token, index: &Expr, value: &Expr) -> R;
+}
+
+impl Expr {
+    pub fn accept<R>(&self, visitor: &mut impl ExprVisitor<R>) -> R {
+        match self {
+            Expr::Literal(literal, token) => visitor.visit_literal(literal, token),
+            Expr::Unary(operator, right) => visitor.visit_unary(operator, right),
+            Expr::Binary(left, operator, right) => visitor.visit_binary(left, operator, right),
+            Expr::Call(callee, paren, arguments) => visitor.visit_call(callee, paren, arguments),
+            Expr::Grouping(paren, expr) => visitor.visit_grouping(paren, expr),
+            Expr::Variable(name, id) => visitor.visit_variable(name, *id),
+            Expr::Assign(name, value, id) => visitor.visit_assign(name, value, *id),
+            Expr::Logical(left, operator, right) => visitor.visit_logical(left, operator, right),
+            Expr::Ternary(condition, question, then, otherwise) => visitor.visit_ternary(condition, question, then, otherwise),
+            Expr::Get(object, name) => visitor.visit_get(object, name),
+            Expr::Set(object, name, value) => visitor.visit_set(object, name, value),
+            Expr::This(keyword, id) => visitor.visit_this(keyword, *id),
+            Expr::Super(keyword, method, id) => visitor.visit_super(keyword, method, *id),
+            Expr::Lambda(keyword, params, body) => visitor.visit_lambda(keyword, params, body),
+            Expr::Array(bracket, elements) => visitor.visit_array(bracket, elements),
+            Expr::Map(brace, entries) => visitor.visit_map(brace, entries),
+            Expr::Index(object, bracket, index) => visitor.visit_index(object, bracket, index),
+            Expr::SetIndex(object, bracket, index, value) => visitor.visit_set_index(object, bracket, index, value),
+        }
+    }
+
+    // The token an expression is reported at: its operator, name or keyword
+    pub fn token(&self) -> &Token {
+        match self {
+            Expr::Literal(_, token) => token,
+            Expr::Unary(operator, _) => operator,
+            Expr::Binary(_, operator, _) => operator,
+            Expr::Call(_, paren, _) => paren,
+            Expr::Grouping(paren, _) => paren,
+            Expr::Variable(name, _) => name,
+            Expr::Assign(name, _, _) => name,
+            Expr::Logical(_, operator, _) => operator,
+            Expr::Ternary(_, question, _, _) => question,
+            Expr::Get(_, name) => name,
+            Expr::Set(_, name, _) => name,
+            Expr::This(keyword, _) => keyword,
+            Expr::Super(keyword, _, _) => keyword,
+            Expr::Lambda(keyword, _, _) => keyword,
+            Expr::Array(bracket, _) => bracket,
+            Expr::Map(brace, _) => brace,
+            Expr::Index(_, bracket, _) => bracket,
+            Expr::SetIndex(_, bracket, _, _) => bracket,
+        }
+    }
 }
 
 impl std::fmt::Display for Expr {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Expr::Literal(l) => {fmt.write_str(format!("{}", l.to_string()).as_str())},
+            // Numbers keep the text they were written as, 1.50 stays 1.50
+            Expr::Literal(Literal::Number(_), t) => fmt.write_str(t.lexeme.as_str()),
+            Expr::Literal(l, _) => {fmt.write_str(format!("{}", l.to_string()).as_str())},
             Expr::Unary(t,e ) => {fmt.write_str(format!("({}{})", t.lexeme, e.to_string()).as_str())}
             Expr::Binary(e1, t, e2) => {
                 fmt.write_str(format!("({} {} {})", e1.to_string(), t.lexeme, e2.to_string()).as_str())
             }
-            Expr::Variable(t) => {
+            Expr::Variable(t, _) => {
                 fmt.write_str(format!("(variable: {})", t.lexeme).as_str())
             }
             Expr::Logical(e1, t, e2) => {
                 fmt.write_str(format!("({} {} {})", e1.to_string(), t.lexeme, e2.to_string()).as_str())
             }
-            Expr::Assign(t, e) => {
+            Expr::Assign(t, e, _) => {
                 fmt.write_str(format!("({} {} {})", t.to_string(), *t, e.to_string()).as_str())
             }
-            Expr::Grouping(l) => {fmt.write_str(format!("({})", l.to_string().as_str()).as_str())},
-            Expr::Call(_callee, paren, _arguments) => {fmt.write_str(format!("fun {}()", paren.lexeme).as_str())},
+            Expr::Ternary(condition, _, then, otherwise) => {
+                fmt.write_str(format!("({} ? {} : {})", condition, then, otherwise).as_str())
+            }
+            Expr::Get(object, name) => {
+                fmt.write_str(format!("({}.{})", object, name.lexeme).as_str())
+            }
+            Expr::Set(object, name, value) => {
+                fmt.write_str(format!("({}.{} = {})", object, name.lexeme, value).as_str())
+            }
+            Expr::This(_, _) => fmt.write_str("this"),
+            Expr::Super(_, method, _) => fmt.write_str(format!("super.{}", method.lexeme).as_str()),
+            Expr::Lambda(_, params, _body) => {
+                let names: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                fmt.write_str(format!("(fun ({}))", names.join(", ")).as_str())
+            }
+            Expr::Grouping(_, l) => {fmt.write_str(format!("({})", l.to_string().as_str()).as_str())},
+            Expr::Array(_, elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                fmt.write_str(format!("[{}]", elements.join(", ")).as_str())
+            }
+            Expr::Map(_, entries) => {
+                let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                fmt.write_str(format!("{{{}}}", entries.join(", ")).as_str())
+            }
+            Expr::Index(object, _, index) => fmt.write_str(format!("({}[{}])", object, index).as_str()),
+            Expr::SetIndex(object, _, index, value) => {
+                fmt.write_str(format!("({}[{}] = {})", object, index, value).as_str())
+            }
+            Expr::Call(callee, _, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                fmt.write_str(format!("({}({}))", callee, arguments.join(", ")).as_str())
+            }
         }.expect("");
         Ok(())
     }
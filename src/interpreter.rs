@@ -1,7 +1,16 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::environment::Environment;
-use crate::expr::Expr;
+use crate::expr::{Expr, ExprId};
 use crate::stmt::Stmt;
 use crate::token::{Literal, Token, TokenType};
+use crate::vm::{format_number, SrcLocation};
+
+// How many recently evaluated source locations are kept for limit diagnostics
+const TRACE_LENGTH: usize = 8;
 
 
 #[derive(Clone)]
@@ -17,6 +26,88 @@ impl std::fmt::Debug for NativeFunction {
     }
 }
 
+fn clock(_interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(Value::Number(since_epoch.as_secs_f64()))
+}
+
+// Length in UTF-8 bytes of a string, so "é" has length 2. Number of elements of an array or
+// number of keys of a map
+fn len(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.first() {
+        Some(Value::String(s)) => Ok(Value::Number(s.len() as f64)),
+        Some(Value::Array(a)) => Ok(Value::Number(a.borrow().len() as f64)),
+        Some(Value::Map(m)) => Ok(Value::Number(m.borrow().len() as f64)),
+        Some(v) => Err(RuntimeError::InvalidCall(format!("len() expects a string, array or map, got '{}'", v))),
+        None => Err(RuntimeError::InvalidCall(String::from("len() expects a string, array or map"))),
+    }
+}
+
+fn str(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.first() {
+        Some(v) => Ok(Value::String(v.to_string())),
+        None => Err(RuntimeError::InvalidCall(String::from("str() expects a value"))),
+    }
+}
+
+// Like print, without the newline
+fn write(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.first() {
+        Some(v) => {
+            interpreter.emit(v.to_string().as_str());
+            Ok(Value::Null)
+        }
+        None => Err(RuntimeError::InvalidCall(String::from("write() expects a value"))),
+    }
+}
+
+// Shows the prompt and reads one line from the input without its line ending, nil at end of input
+fn input(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.first() {
+        Some(Value::String(prompt)) => interpreter.emit(prompt),
+        Some(v) => return Err(RuntimeError::InvalidCall(format!("input() expects a prompt string, got '{}'", v))),
+        None => return Err(RuntimeError::InvalidCall(String::from("input() expects a prompt string"))),
+    }
+    let mut line = String::new();
+    let read = match &mut interpreter.input {
+        Some(input) => input.read_line(&mut line),
+        None => std::io::stdin().read_line(&mut line),
+    };
+    match read {
+        Ok(0) | Err(_) => Ok(Value::Null),
+        Ok(_) => Ok(Value::String(line.trim_end_matches(['\n', '\r']).to_string())),
+    }
+}
+
+// Parses a string into a number, nil when the string is not a number
+fn num(_interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.first() {
+        Some(Value::String(s)) => match s.trim().parse::<f64>() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => Ok(Value::Null),
+        },
+        Some(v) => Err(RuntimeError::InvalidCall(format!("num() expects a string, got '{}'", v))),
+        None => Err(RuntimeError::InvalidCall(String::from("num() expects a string"))),
+    }
+}
+
+// Fails with the position of the call when the condition is falsey
+fn assert(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match args.into_iter().next() {
+        Some(v) if interpreter.is_truthy(v.clone()) => Ok(Value::Null),
+        Some(v) => Err(interpreter.assertion_failed(format!("'{}' is not truthy", v))),
+        None => Err(RuntimeError::InvalidCall(String::from("assert() expects a value"))),
+    }
+}
+
+fn assert_eq(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    match (args.first(), args.get(1)) {
+        (Some(a), Some(b)) if values_equal(a, b) => Ok(Value::Null),
+        (Some(a), Some(b)) => Err(interpreter.assertion_failed(format!("'{}' is not equal to '{}'", a, b))),
+        _ => Err(RuntimeError::InvalidCall(String::from("assert_eq() expects two values"))),
+    }
+}
+
 trait Callable {
     fn arity(&self, interpreter: &Interpreter) -> usize;
     fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError>;
@@ -34,9 +125,11 @@ impl Callable for NativeFunction {
 #[derive(Clone)]
 pub struct LoxFunction {
     name: String,
-    body: Vec<Stmt>,
+    // Shared by copies of the function value, so its identity survives cloning
+    body: Rc<Vec<Stmt>>,
     params: Vec<Token>,
     arity: usize,
+    closure: Rc<RefCell<Environment>>,
 }
 
 impl Callable for LoxFunction {
@@ -45,28 +138,120 @@ impl Callable for LoxFunction {
     }
 
     fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
-        let mut environment = Environment::default();
+        let environment = Environment::new(Some(Rc::clone(&self.closure)));
         for (i, arg) in args.iter().enumerate() {
-            environment.define(self.params[i].lexeme.clone(), Some(arg.clone()));
+            environment.borrow_mut().define(self.params[i].lexeme.clone(), Some(arg.clone()));
+        }
+
+        match interpreter.interpret_block(&self.body, Some(environment)) {
+            Ok(()) => Ok(Value::Null),
+            Err(RuntimeError::Return(value)) => Ok(value),
+            Err(e) => Err(e),
         }
+    }
+}
+
+impl LoxFunction {
+    pub fn new(name: String, params: Vec<Token>, body: Vec<Stmt>, closure: Rc<RefCell<Environment>>) -> LoxFunction {
+        LoxFunction{name, body: Rc::new(body), arity: params.len(), params, closure}
+    }
+
+    // Returns a copy of the method whose closure has `this` bound to the instance
+    fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        let environment = Environment::new(Some(Rc::clone(&self.closure)));
+        environment.borrow_mut().define(String::from("this"), Some(Value::Instance(instance)));
+        LoxFunction{closure: environment, ..self.clone()}
+    }
+}
+
+impl std::fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LoxFunction({})", self.name)
+    }
+}
+
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, LoxFunction>,
+}
 
-        // Note: Not modifying outer variables from inside of function
-        interpreter.interpret_block(self.body.clone(), Some(Box::new(environment.clone())));
+impl LoxClass {
+    pub fn new(name: String, superclass: Option<Rc<LoxClass>>, methods: HashMap<String, LoxFunction>) -> LoxClass {
+        LoxClass{name, superclass, methods}
+    }
+
+    // Only the methods declared by this class itself, see find_method for inherited ones
+    pub fn methods(&self) -> &HashMap<String, LoxFunction> {
+        &self.methods
+    }
+
+    // Looks in this class first, then up the superclass chain, so the nearest override wins
+    pub fn find_method(&self, name: &str) -> Option<LoxFunction> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(method.clone());
+        }
+        match &self.superclass {
+            Some(superclass) => superclass.find_method(name),
+            None => None,
+        }
+    }
+}
 
-        Ok(Value::Null)
+impl std::fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LoxClass({})", self.name)
     }
 }
 
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
 
+impl LoxInstance {
+    fn new(class: Rc<LoxClass>) -> Rc<RefCell<LoxInstance>> {
+        Rc::new(RefCell::new(LoxInstance{class, fields: HashMap::new()}))
+    }
+
+    // Takes the shared handle rather than &self so methods can be bound to it
+    fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> Result<Value, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        match method {
+            Some(method) => Ok(Value::LoxFunction(method.bind(Rc::clone(instance)))),
+            None => Err(RuntimeError::UndefinedProperty{name: name.lexeme.clone(), line: name.line, col: name.col}),
+        }
+    }
+
+    fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+
+#[derive(Debug)]
 pub enum Value {
     Bool(bool),
     Null,
     Number(f64),
     String(String),
     NativeFunction(NativeFunction),
-    LoxFunction(LoxFunction)
+    LoxFunction(LoxFunction),
+    Class(Rc<LoxClass>),
+    // Instances are shared: every copy of the value refers to the same fields
+    Instance(Rc<RefCell<LoxInstance>>),
+    // Arrays are shared the same way
+    Array(Rc<RefCell<Vec<Value>>>),
+    // Maps too, keyed by the stringified key
+    Map(Rc<RefCell<HashMap<String, Value>>>),
 }
 
+// Assigning or passing a value clones it. Numbers, booleans and strings are copied, so the copies are
+// independent. Instances, arrays and maps only clone the Rc, so every copy sees the same contents.
 impl Clone for Value {
     fn clone(&self) -> Value {
         match self {
@@ -76,178 +261,825 @@ impl Clone for Value {
             Value::String(s) => Value::String(s.clone()),
             Value::LoxFunction(f) => Value::LoxFunction((*f).clone()),
             Value::NativeFunction(f) => Value::NativeFunction((*f).clone()),
+            Value::Class(c) => Value::Class(Rc::clone(c)),
+            Value::Instance(i) => Value::Instance(Rc::clone(i)),
+            Value::Array(a) => Value::Array(Rc::clone(a)),
+            Value::Map(m) => Value::Map(Rc::clone(m)),
+        }
+    }
+}
+
+// Lox equality: values of different types are never equal. Numbers compare with IEEE ==, so NaN
+// is not equal to itself. Functions, classes, instances, arrays and maps compare by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(n1), Value::Number(n2)) => n1 == n2,
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            (Value::Null, Value::Null) => true,
+            (Value::LoxFunction(f1), Value::LoxFunction(f2)) =>
+                Rc::ptr_eq(&f1.body, &f2.body) && Rc::ptr_eq(&f1.closure, &f2.closure),
+            (Value::NativeFunction(f1), Value::NativeFunction(f2)) => f1.name == f2.name,
+            (Value::Class(c1), Value::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Value::Instance(i1), Value::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            (Value::Array(a1), Value::Array(a2)) => Rc::ptr_eq(a1, a2),
+            (Value::Map(m1), Value::Map(m2)) => Rc::ptr_eq(m1, m2),
+            _ => false,
         }
     }
 }
 
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.fmt_nested(f, &mut vec![])
+    }
+}
+
+impl Value {
+    // `printing` holds the arrays and maps enclosing this value, one that contains itself is
+    // shown as [...] or {...} instead of being printed forever
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter, printing: &mut Vec<*const ()>) -> std::fmt::Result {
         match self {
-            Value::Number(n) => f.write_str(format!("{}", n).as_str()),
+            Value::Number(n) => f.write_str(format_number(*n).as_str()),
             Value::String(s) => f.write_str(s.as_str()),
-            Value::Null => f.write_str("Null"),
+            Value::Null => f.write_str("nil"),
             Value::Bool(b) => f.write_str(b.to_string().as_str()),
-            Value::LoxFunction(fu) => f.write_str(fu.name.as_str()),
-            Value::NativeFunction(fu) => f.write_str(fu.name.as_str()),
+            Value::LoxFunction(fu) => {
+                let name = if fu.name.is_empty() { "anonymous" } else { fu.name.as_str() };
+                let params: Vec<&str> = fu.params.iter().map(|p| p.lexeme.as_str()).collect();
+                f.write_str(format!("<fn {}({})>", name, params.join(", ")).as_str())
+            }
+            Value::NativeFunction(fu) => f.write_str(format!("<native fn {}>", fu.name).as_str()),
+            Value::Class(c) => f.write_str(c.name.as_str()),
+            Value::Instance(i) => f.write_str(format!("{} instance", i.borrow().class.name).as_str()),
+            Value::Array(a) => {
+                let ptr = Rc::as_ptr(a) as *const ();
+                if printing.contains(&ptr) {
+                    return f.write_str("[...]");
+                }
+                printing.push(ptr);
+                f.write_str("[")?;
+                for (i, element) in a.borrow().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    element.fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                f.write_str("]")
+            }
+            Value::Map(m) => {
+                let ptr = Rc::as_ptr(m) as *const ();
+                if printing.contains(&ptr) {
+                    return f.write_str("{...}");
+                }
+                printing.push(ptr);
+                // Sorted by key so the same map always prints the same way
+                let m = m.borrow();
+                let mut keys: Vec<&String> = m.keys().collect();
+                keys.sort();
+                f.write_str("{")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    m[*key].fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                f.write_str("}")
+            }
         }
     }
 }
 
+// Array indexes are whole numbers within the array's bounds
+fn array_index(len: usize, index: &Value, bracket: &Token) -> Result<usize, RuntimeError> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < len => Ok(*n as usize),
+        _ => Err(RuntimeError::InvalidIndex{index: index.to_string(), line: bracket.line, col: bracket.col}),
+    }
+}
+
+// Bitwise operators work on whole numbers, converted to i64 and back
+fn integer_operand(n: f64, operator: &Token) -> Result<i64, RuntimeError> {
+    if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Ok(n as i64)
+    } else {
+        Err(RuntimeError::InvalidBitwiseOperand{value: format_number(n), line: operator.line, col: operator.col})
+    }
+}
+
+fn bitwise(n1: f64, operator: &Token, n2: f64) -> Result<Value, RuntimeError> {
+    let a = integer_operand(n1, operator)?;
+    let b = integer_operand(n2, operator)?;
+    // Shifting by a negative amount or the whole width or more is an error rather than a wrap
+    let shift = || match u32::try_from(b) {
+        Ok(s) if s < i64::BITS => Ok(s),
+        _ => Err(RuntimeError::InvalidBitwiseOperand{value: format_number(n2), line: operator.line, col: operator.col}),
+    };
+    let result = match operator.token_type {
+        TokenType::Ampersand => a & b,
+        TokenType::Pipe => a | b,
+        TokenType::Caret => a ^ b,
+        TokenType::LessLess => a << shift()?,
+        TokenType::GreaterGreater => a >> shift()?,
+        _ => return Err(RuntimeError::BinaryOperationError{line: operator.line, col: operator.col}),
+    };
+    Ok(Value::Number(result as f64))
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    a == b
+}
+
 #[derive(Debug)]
 pub enum RuntimeError {
-    BinaryOperationError,
+    BinaryOperationError {
+        line: usize,
+        col: usize,
+    },
     // NotImplementedError,
     VariableNotFound,
     VariableNotInitialized,
+    VariableIsConstant,
     LogicalOperatorError,
     InvalidCall(String),
+    DivisionByZero {
+        line: usize,
+        col: usize,
+    },
+    OnlyInstancesHaveProperties {
+        line: usize,
+        col: usize,
+    },
+    OnlyArraysAndMapsCanBeIndexed {
+        line: usize,
+        col: usize,
+    },
+    NotIterable {
+        line: usize,
+        col: usize,
+    },
+    InvalidIndex {
+        index: String,
+        line: usize,
+        col: usize,
+    },
+    InvalidBitwiseOperand {
+        value: String,
+        line: usize,
+        col: usize,
+    },
+    UndefinedProperty {
+        name: String,
+        line: usize,
+        col: usize,
+    },
+    SuperclassMustBeClass {
+        line: usize,
+        col: usize,
+    },
+    OperandMustBeNumber {
+        line: usize,
+        col: usize,
+    },
+    RecursionLimitExceeded {
+        limit: usize,
+        trace: Vec<SrcLocation>,
+    },
+    StepLimitExceeded {
+        limit: u64,
+        trace: Vec<SrcLocation>,
+    },
+    AssertionFailed {
+        message: String,
+        line: usize,
+        col: usize,
+    },
+    UndefinedVariable {
+        name: String,
+        line: usize,
+        col: usize,
+    },
+    // Declared with `var x;` and read before anything was assigned
+    UninitializedVariable {
+        name: String,
+        line: usize,
+        col: usize,
+    },
+    AssignToConst {
+        name: String,
+        line: usize,
+        col: usize,
+    },
+    // Not an error: unwinds the interpreter from a `return` up to the enclosing call
+    Return(Value),
+    // Not errors either: unwind from `break`/`continue` up to the enclosing loop
+    Break,
+    Continue,
+}
+
+impl RuntimeError {
+    // The environment only knows names, so lookups failing on a variable use get its position here
+    fn at_variable(self, name: &Token) -> RuntimeError {
+        match self {
+            RuntimeError::VariableNotFound =>
+                RuntimeError::UndefinedVariable{name: name.lexeme.clone(), line: name.line, col: name.col},
+            RuntimeError::VariableNotInitialized =>
+                RuntimeError::UninitializedVariable{name: name.lexeme.clone(), line: name.line, col: name.col},
+            RuntimeError::VariableIsConstant =>
+                RuntimeError::AssignToConst{name: name.lexeme.clone(), line: name.line, col: name.col},
+            e => e,
+        }
+    }
 }
 
 impl std::fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            RuntimeError::BinaryOperationError => f.write_str("BinaryOperationError"),
+            RuntimeError::BinaryOperationError{line, col} =>
+                f.write_str(format!("BinaryOperationError at line: {}:{}", line, col).as_str()),
             // InterpreterError::NotImplementedError => f.write_str("NotImplementedError"),
             RuntimeError::VariableNotFound => f.write_str("VariableNotFound"),
             RuntimeError::VariableNotInitialized => f.write_str("VariableNotInitialized"),
+            RuntimeError::VariableIsConstant => f.write_str("VariableIsConstant"),
             RuntimeError::LogicalOperatorError => f.write_str("LogicalOperatorError"),
             RuntimeError::InvalidCall(m) => f.write_str(format!("InvalidCall: {}", m).as_str()),
+            RuntimeError::DivisionByZero{line, col} =>
+                f.write_str(format!("DivisionByZero at line: {}:{}", line, col).as_str()),
+            RuntimeError::OnlyInstancesHaveProperties{line, col} =>
+                f.write_str(format!("OnlyInstancesHaveProperties at line: {}:{}", line, col).as_str()),
+            RuntimeError::OnlyArraysAndMapsCanBeIndexed{line, col} =>
+                f.write_str(format!("OnlyArraysAndMapsCanBeIndexed at line: {}:{}", line, col).as_str()),
+            RuntimeError::NotIterable{line, col} =>
+                f.write_str(format!("NotIterable at line: {}:{}", line, col).as_str()),
+            RuntimeError::InvalidIndex{index, line, col} =>
+                f.write_str(format!("InvalidIndex '{}' at line: {}:{}", index, line, col).as_str()),
+            RuntimeError::InvalidBitwiseOperand{value, line, col} =>
+                f.write_str(format!("InvalidBitwiseOperand '{}' at line: {}:{}", value, line, col).as_str()),
+            RuntimeError::UndefinedProperty{name, line, col} =>
+                f.write_str(format!("UndefinedProperty '{}' at line: {}:{}", name, line, col).as_str()),
+            RuntimeError::SuperclassMustBeClass{line, col} =>
+                f.write_str(format!("SuperclassMustBeClass at line: {}:{}", line, col).as_str()),
+            RuntimeError::OperandMustBeNumber{line, col} =>
+                f.write_str(format!("OperandMustBeNumber at line: {}:{}", line, col).as_str()),
+            RuntimeError::RecursionLimitExceeded{limit, trace} => {
+                f.write_str(format!("RecursionLimitExceeded: more than {} nested calls. Most recent locations:", limit).as_str())?;
+                for location in trace {
+                    f.write_str(format!("\n\t{}", location).as_str())?;
+                }
+                Ok(())
+            }
+            RuntimeError::StepLimitExceeded{limit, trace} => {
+                f.write_str(format!("StepLimitExceeded: more than {} statements executed. Most recent locations:", limit).as_str())?;
+                for location in trace {
+                    f.write_str(format!("\n\t{}", location).as_str())?;
+                }
+                Ok(())
+            }
+            RuntimeError::UndefinedVariable{name, line, col} =>
+                f.write_str(format!("UndefinedVariable '{}' at line: {}:{}", name, line, col).as_str()),
+            RuntimeError::UninitializedVariable{name, line, col} =>
+                f.write_str(format!("UninitializedVariable '{}' at line: {}:{}", name, line, col).as_str()),
+            RuntimeError::AssignToConst{name, line, col} =>
+                f.write_str(format!("AssignToConst '{}' at line: {}:{}", name, line, col).as_str()),
+            RuntimeError::AssertionFailed{message, line, col} =>
+                f.write_str(format!("AssertionFailed: {} at line: {}:{}", message, line, col).as_str()),
+            RuntimeError::Return(v) => f.write_str(format!("Return outside of function: {}", v).as_str()),
+            RuntimeError::Break => f.write_str("Break outside of loop"),
+            RuntimeError::Continue => f.write_str("Continue outside of loop"),
         }
     }
 }
 
-#[derive(Default)]
 pub struct Interpreter {
-    environment: Box<Environment>,
+    environment: Rc<RefCell<Environment>>,
+    dump_scopes: bool,
+    checked_div: bool,
+    // Echo the value of top-level expression statements, as an interactive prompt does
+    repl_mode: bool,
+    // Log executed statements and expression results to stderr, indented by block depth
+    trace: bool,
+    block_depth: usize,
+    max_call_depth: Option<usize>,
+    call_depth: usize,
+    // Statements executed by the current interpret() call, bounded by step_limit
+    step_limit: Option<u64>,
+    steps: u64,
+    recent_locations: VecDeque<SrcLocation>,
+    // Position of the innermost call being made, so natives can report errors at it
+    call_location: SrcLocation,
+    // Scope depth of each resolved local variable use, see Resolver
+    locals: HashMap<ExprId, usize>,
+    // Where print, echoed REPL values, scope dumps and the write() and input() natives go
+    output: Box<dyn Write>,
+    // Where the input() native reads from, stdin when unset. Stdin isn't wrapped in a reader of its
+    // own, which would buffer lines away from the REPL
+    input: Option<Box<dyn BufRead>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        let mut interpreter = Interpreter {
+            environment: Environment::new(None),
+            dump_scopes: false,
+            checked_div: false,
+            repl_mode: false,
+            trace: false,
+            block_depth: 0,
+            max_call_depth: None,
+            call_depth: 0,
+            step_limit: None,
+            steps: 0,
+            recent_locations: VecDeque::new(),
+            call_location: SrcLocation{line: 0, col: 0},
+            locals: HashMap::new(),
+            output: Box::new(std::io::stdout()),
+            input: None,
+        };
+        interpreter.define_natives();
+        interpreter
+    }
+}
+
+// Runs a block's statements in its own environment and puts the enclosing one back when dropped,
+// so errors, break and return can't leave the REPL inside the failed block's scope
+struct ScopeGuard<'a> {
+    interpreter: &'a mut Interpreter,
+    previous: Rc<RefCell<Environment>>,
+}
+
+impl<'a> ScopeGuard<'a> {
+    fn enter(interpreter: &'a mut Interpreter, environment: Rc<RefCell<Environment>>) -> ScopeGuard<'a> {
+        interpreter.block_depth += 1;
+        let previous = std::mem::replace(&mut interpreter.environment, environment);
+        ScopeGuard{interpreter, previous}
+    }
+}
+
+impl std::ops::Deref for ScopeGuard<'_> {
+    type Target = Interpreter;
+    fn deref(&self) -> &Interpreter {
+        self.interpreter
+    }
+}
+
+impl std::ops::DerefMut for ScopeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Interpreter {
+        self.interpreter
+    }
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        if self.interpreter.dump_scopes {
+            let dump = self.interpreter.environment.borrow().dump();
+            self.interpreter.emit(dump.as_str());
+        }
+        std::mem::swap(&mut self.interpreter.environment, &mut self.previous);
+        self.interpreter.block_depth -= 1;
+    }
 }
 
 impl Interpreter {
-    pub fn interpret(&mut self, statements: Vec<Stmt>) {
-        self.environment = Box::new(Environment::default());
-        self.environment.enclosing(None);
-        for statement in statements {
-            self.execute(statement);
+    pub fn resolve(&mut self, locals: HashMap<ExprId, usize>) {
+        self.locals.extend(locals);
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    // Output errors are ignored, as there is nowhere left to report them
+    fn emit(&mut self, text: &str) {
+        let _ = self.output.write_all(text.as_bytes());
+        let _ = self.output.flush();
+    }
+
+    pub fn enable_scope_dump(&mut self) {
+        self.dump_scopes = true;
+    }
+
+    pub fn enable_checked_div(&mut self) {
+        self.checked_div = true;
+    }
+
+    pub fn enable_repl_mode(&mut self) {
+        self.repl_mode = true;
+    }
+
+    pub fn enable_trace(&mut self) {
+        self.trace = true;
+    }
+
+    pub fn set_max_call_depth(&mut self, limit: usize) {
+        self.max_call_depth = Some(limit);
+    }
+
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = Some(limit);
+    }
+
+    // Only kept while a limit is configured, so normal runs pay nothing for it
+    fn record_location(&mut self, token: &Token) {
+        if self.max_call_depth.is_none() && self.step_limit.is_none() {
+            return;
+        }
+        if self.recent_locations.len() == TRACE_LENGTH {
+            self.recent_locations.pop_front();
+        }
+        self.recent_locations.push_back(SrcLocation{line: token.line, col: token.col});
+    }
+
+    // Globals persist between calls, so a REPL can feed one line at a time.
+    // Stops at the first runtime error and hands it to the caller to report.
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
+        self.steps = 0;
+        for statement in &statements {
+            match statement {
+                Stmt::Expression(e) if self.repl_mode => {
+                    let value = self.interpret_expr(e)?;
+                    self.emit(format!("{}\n", value).as_str());
+                }
+                _ => self.execute(statement)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn assertion_failed(&self, message: String) -> RuntimeError {
+        RuntimeError::AssertionFailed{message, line: self.call_location.line, col: self.call_location.col}
+    }
+
+    fn define_natives(&mut self) {
+        let natives = [
+            NativeFunction{name: String::from("clock"), arity: 0, callable: clock},
+            NativeFunction{name: String::from("len"), arity: 1, callable: len},
+            NativeFunction{name: String::from("str"), arity: 1, callable: str},
+            NativeFunction{name: String::from("num"), arity: 1, callable: num},
+            NativeFunction{name: String::from("write"), arity: 1, callable: write},
+            NativeFunction{name: String::from("input"), arity: 1, callable: input},
+            NativeFunction{name: String::from("assert"), arity: 1, callable: assert},
+            NativeFunction{name: String::from("assert_eq"), arity: 2, callable: assert_eq},
+        ];
+        for native in natives {
+            self.define_native(native);
         }
     }
 
-    fn execute(&mut self, statement: Stmt) {
+    // Makes a Rust function callable from Lox as a global, replacing any global of the same name
+    pub fn define_native(&mut self, native: NativeFunction) {
+        self.environment.borrow_mut().define(native.name.clone(), Some(Value::NativeFunction(native)));
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(RuntimeError::StepLimitExceeded {
+                    limit,
+                    trace: self.recent_locations.iter().cloned().collect(),
+                });
+            }
+            self.steps += 1;
+        }
+        if self.trace {
+            // Blocks span several lines, their statements are traced as they execute
+            let text = statement.to_string();
+            eprintln!("{}{}", "  ".repeat(self.block_depth), text.lines().next().unwrap_or_default());
+        }
         match statement {
-            Stmt::Print(s) => println!("{}", self.interpret_expr(Box::new(s)).expect("Failed to interpret")),
-            Stmt::Block(b) => self.interpret_block(b, None),
+            Stmt::Print(s) => {
+                let value = self.interpret_expr(s)?;
+                self.emit(format!("{}\n", value).as_str());
+            }
+            Stmt::Block(b) => self.interpret_block(b, None)?,
             Stmt::Expression(e) => {
-                let res = self.interpret_expr(Box::new(e)).expect("Failed to interpret");
-                println!("{}", res)
+                self.interpret_expr(e)?;
             },
-            Stmt::VarDeclaration(n, e) => {
+            Stmt::VarDeclaration(n, e, constant) => {
                 let val = match e {
-                    Some(ex) =>  Some(self.interpret_expr(Box::new(ex))
-                        .expect("Failed to interpret variable declaration")),
+                    Some(ex) =>  Some(self.interpret_expr(ex)?),
                     None => None,
                 };
-                self.environment.define(n.lexeme, val);
+                if *constant {
+                    self.environment.borrow_mut().define_constant(n.lexeme.clone(), val);
+                } else {
+                    self.environment.borrow_mut().define(n.lexeme.clone(), val);
+                }
             }
             Stmt::If(c, b1, b2) => {
-                let condition = match self.interpret_expr(Box::new(c)) {
-                    Ok(c) => c,
-                    Err(e) => {eprintln!("Failed interpreting condition:  {}", e); return;}
-                };
+                let condition = self.interpret_expr(c)?;
                 if self.is_truthy(condition) {
-                    self.execute(*b1);
-                } else if b2.is_some() {
-                    self.execute(*b2.unwrap());
+                    self.execute(b1)?;
+                } else if let Some(b2) = b2 {
+                    self.execute(b2)?;
                 }
             }
-            Stmt::While(condition, body) => {
+            Stmt::While(keyword, condition, body, increment) => {
                 loop {
-                    if let Ok(result) = self.interpret_expr(Box::new(condition.clone())) {
-                        if self.is_truthy(result) {
-                            self.execute(*body.clone());
-                        } else {
-                            break;
-                        }
-                    } else {
+                    self.record_location(keyword);
+                    let result = self.interpret_expr(condition)?;
+                    if !self.is_truthy(result) {
                         break;
                     }
+                    match self.execute(body) {
+                        Ok(()) | Err(RuntimeError::Continue) => {}
+                        Err(RuntimeError::Break) => break,
+                        Err(e) => return Err(e),
+                    }
+                    if let Some(increment) = increment {
+                        self.interpret_expr(increment)?;
+                    }
+                }
+            }
+            // Iterates over the elements the array had when the loop started
+            Stmt::ForEach(name, iterable, body) => {
+                let location = iterable.token().clone();
+                let elements = match self.interpret_expr(iterable)? {
+                    Value::Array(elements) => elements.borrow().clone(),
+                    _ => return Err(RuntimeError::NotIterable{line: location.line, col: location.col}),
+                };
+                for element in elements {
+                    self.record_location(name);
+                    let environment = Environment::new(Some(Rc::clone(&self.environment)));
+                    environment.borrow_mut().define(name.lexeme.clone(), Some(element));
+                    match self.interpret_block(std::slice::from_ref(body.as_ref()), Some(environment)) {
+                        Ok(()) | Err(RuntimeError::Continue) => {}
+                        Err(RuntimeError::Break) => break,
+                        Err(e) => return Err(e),
+                    }
                 }
             }
-            Stmt::Function(name, ref arguments, body) => {
-                let func = LoxFunction{name: name.lexeme.clone(), body, params: arguments.clone(), arity: arguments.len()};
-                self.environment.define(name.lexeme, Some(Value::LoxFunction(func)));
+            Stmt::Break => return Err(RuntimeError::Break),
+            Stmt::Continue => return Err(RuntimeError::Continue),
+            Stmt::Function(name, arguments, body) => {
+                let func = LoxFunction::new(name.lexeme.clone(), arguments.clone(), body.clone(), Rc::clone(&self.environment));
+                self.environment.borrow_mut().define(name.lexeme.clone(), Some(Value::LoxFunction(func)));
+            }
+            Stmt::Class(name, superclass, methods) => {
+                let mut superclass_ = None;
+                let mut closure = Rc::clone(&self.environment);
+                if let Some(superclass @ Expr::Variable(superclass_name, _)) = superclass {
+                    let value = self.interpret_expr(superclass)?;
+                    let Value::Class(class) = value else {
+                        return Err(RuntimeError::SuperclassMustBeClass{line: superclass_name.line, col: superclass_name.col});
+                    };
+                    // Methods close over an extra scope holding `super`, mirrored by the Resolver
+                    let environment = Environment::new(Some(closure));
+                    environment.borrow_mut().define(String::from("super"), Some(Value::Class(Rc::clone(&class))));
+                    closure = environment;
+                    superclass_ = Some(class);
+                }
+
+                let mut class_methods = HashMap::new();
+                for method in methods {
+                    if let Stmt::Function(method_name, params, body) = method {
+                        let func = LoxFunction::new(method_name.lexeme.clone(), params.clone(), body.clone(), Rc::clone(&closure));
+                        class_methods.insert(method_name.lexeme.clone(), func);
+                    }
+                }
+                let class = LoxClass::new(name.lexeme.clone(), superclass_, class_methods);
+                self.environment.borrow_mut().define(name.lexeme.clone(), Some(Value::Class(Rc::new(class))));
+            }
+            Stmt::Return(_keyword, e) => {
+                let value = match e {
+                    Some(ex) => self.interpret_expr(ex)?,
+                    None => Value::Null,
+                };
+                return Err(RuntimeError::Return(value));
             }
         }
+        Ok(())
     }
 
-    fn interpret_block(&mut self, block: Vec<Stmt>, environment: Option<Box<Environment>>) {
-        let prev_env = self.environment.clone();
-        if let Some(e) = environment {
-            self.environment = e;
-        } else {
-            self.environment = Box::new(Environment::default());
-            self.environment.enclosing(Some(prev_env));
-        }
+    // Runs the block in the given environment (a call's parameters), or in a fresh scope nested in the current one
+    fn interpret_block(&mut self, block: &[Stmt], environment: Option<Rc<RefCell<Environment>>>) -> Result<(), RuntimeError> {
+        let environment = environment.unwrap_or_else(|| Environment::new(Some(Rc::clone(&self.environment))));
+        let mut scope = ScopeGuard::enter(self, environment);
         for stmt in block {
-            self.execute(stmt)
+            scope.execute(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn interpret_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        if !self.trace {
+            return self.evaluate(expr);
         }
-        if let Some(enclosing) = self.environment.get_enclosing() {
-            self.environment = enclosing;
+        let text = expr.to_string();
+        let result = self.evaluate(expr);
+        if let Ok(value) = &result {
+            eprintln!("{}{} => {}", "  ".repeat(self.block_depth + 1), text, value);
         }
+        result
     }
 
-    // TODO is it better to use non-boxed expr argument?
-    fn interpret_expr(&mut self, expr: Box<Expr>) -> Result<Value, RuntimeError> {
-        match *expr {
-            Expr::Literal(literal) => self.interpret_literal(literal),
-            Expr::Unary(op, e) => self.interpret_unary(op.token_type, e),
-            Expr::Binary(left, operator, right) =>
-                self.interpret_binary(left, operator.token_type, right),
-            Expr::Grouping(e) => self.interpret_expr(e),
-            Expr::Variable(v) => self.environment.get(v.lexeme),
-            Expr::Assign(literal, e) => {
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Literal(literal, _) => self.interpret_literal(literal),
+            Expr::Unary(op, e) => {
+                self.record_location(op);
+                self.interpret_unary(op, e)
+            }
+            Expr::Binary(left, operator, right) => {
+                self.record_location(operator);
+                self.interpret_binary(left, operator, right)
+            }
+            Expr::Grouping(_, e) => self.interpret_expr(e),
+            Expr::Variable(v, id) => {
+                self.record_location(v);
+                let value = match self.locals.get(id) {
+                    Some(depth) => self.environment.borrow().get_at(*depth, v.lexeme.clone()),
+                    None => self.environment.borrow().get_global(v.lexeme.clone()),
+                };
+                value.map_err(|e| e.at_variable(v))
+            }
+            Expr::Assign(literal, e, id) => {
+                self.record_location(literal);
                 let res = self.interpret_expr(e)?;
-                self.environment.assign(literal.lexeme, res.clone())?;
+                let assigned = match self.locals.get(id) {
+                    Some(depth) => self.environment.borrow_mut().assign_at(*depth, literal.lexeme.clone(), res.clone()),
+                    None => self.environment.borrow_mut().assign_global(literal.lexeme.clone(), res.clone()),
+                };
+                assigned.map_err(|e| e.at_variable(literal))?;
                 Ok(res)
             },
-            Expr::Logical(left, operator, right) =>
-                self.interpret_logical(left, operator.token_type, right),
-            Expr::Call(callee, _paren, arguments) => {
+            Expr::Get(object, name) => {
+                self.record_location(name);
+                match self.interpret_expr(object)? {
+                    Value::Instance(instance) => LoxInstance::get(&instance, name),
+                    _ => Err(RuntimeError::OnlyInstancesHaveProperties{line: name.line, col: name.col}),
+                }
+            }
+            Expr::Set(object, name, value) => {
+                self.record_location(name);
+                match self.interpret_expr(object)? {
+                    Value::Instance(instance) => {
+                        let value = self.interpret_expr(value)?;
+                        instance.borrow_mut().set(name, value.clone());
+                        Ok(value)
+                    }
+                    _ => Err(RuntimeError::OnlyInstancesHaveProperties{line: name.line, col: name.col}),
+                }
+            }
+            Expr::Array(_, elements) => {
+                let mut values = vec![];
+                for element in elements {
+                    values.push(self.interpret_expr(element)?);
+                }
+                Ok(Value::Array(Rc::new(RefCell::new(values))))
+            }
+            Expr::Map(_, entries) => {
+                let mut map = HashMap::new();
+                for (key, value) in entries {
+                    let key = self.interpret_expr(key)?.to_string();
+                    map.insert(key, self.interpret_expr(value)?);
+                }
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            Expr::Index(object, bracket, index) => {
+                self.record_location(bracket);
+                let object = self.interpret_expr(object)?;
+                let index = self.interpret_expr(index)?;
+                match object {
+                    Value::Array(elements) => {
+                        let i = array_index(elements.borrow().len(), &index, bracket)?;
+                        Ok(elements.borrow()[i].clone())
+                    }
+                    // A missing key reads as nil
+                    Value::Map(entries) => Ok(entries.borrow().get(&index.to_string()).cloned().unwrap_or(Value::Null)),
+                    _ => Err(RuntimeError::OnlyArraysAndMapsCanBeIndexed{line: bracket.line, col: bracket.col}),
+                }
+            }
+            Expr::SetIndex(object, bracket, index, value) => {
+                self.record_location(bracket);
+                let object = self.interpret_expr(object)?;
+                let index = self.interpret_expr(index)?;
+                let value = self.interpret_expr(value)?;
+                match object {
+                    Value::Array(elements) => {
+                        let i = array_index(elements.borrow().len(), &index, bracket)?;
+                        elements.borrow_mut()[i] = value.clone();
+                        Ok(value)
+                    }
+                    Value::Map(entries) => {
+                        entries.borrow_mut().insert(index.to_string(), value.clone());
+                        Ok(value)
+                    }
+                    _ => Err(RuntimeError::OnlyArraysAndMapsCanBeIndexed{line: bracket.line, col: bracket.col}),
+                }
+            }
+            Expr::This(keyword, id) => {
+                match self.locals.get(id) {
+                    Some(depth) => self.environment.borrow().get_at(*depth, keyword.lexeme.clone()),
+                    None => self.environment.borrow().get_global(keyword.lexeme.clone()),
+                }
+            }
+            Expr::Super(keyword, method, id) => {
+                let Some(depth) = self.locals.get(id).copied() else {
+                    return Err(RuntimeError::VariableNotFound);
+                };
+                let Value::Class(superclass) = self.environment.borrow().get_at(depth, keyword.lexeme.clone())? else {
+                    return Err(RuntimeError::SuperclassMustBeClass{line: keyword.line, col: keyword.col});
+                };
+                // `this` lives in the scope just inside the one holding `super`
+                let Value::Instance(instance) = self.environment.borrow().get_at(depth - 1, String::from("this"))? else {
+                    return Err(RuntimeError::VariableNotFound);
+                };
+                match superclass.find_method(&method.lexeme) {
+                    Some(m) => Ok(Value::LoxFunction(m.bind(instance))),
+                    None => Err(RuntimeError::UndefinedProperty{name: method.lexeme.clone(), line: method.line, col: method.col}),
+                }
+            }
+            Expr::Logical(left, operator, right) => {
+                self.record_location(operator);
+                self.interpret_logical(left, operator.token_type, right)
+            }
+            Expr::Lambda(_, params, body) => {
+                Ok(Value::LoxFunction(LoxFunction::new(String::new(), params.clone(), body.clone(), Rc::clone(&self.environment))))
+            }
+            Expr::Ternary(condition, _, then, otherwise) => {
+                let condition = self.interpret_expr(condition)?;
+                if self.is_truthy(condition) {
+                    self.interpret_expr(then)
+                } else {
+                    self.interpret_expr(otherwise)
+                }
+            }
+            Expr::Call(callee, paren, arguments) => {
+                self.record_location(paren);
                 let callee = self.interpret_expr(callee)?;
                 let mut arguments_ = vec![];
                 for argument in arguments {
-                    arguments_.push(self.interpret_expr(Box::new(argument))?);
+                    arguments_.push(self.interpret_expr(argument)?);
                 }
 
-                if let Value::LoxFunction(function) = callee {
-                    function.call(self, arguments_)
-                } else {
-                    Err(RuntimeError::InvalidCall(String::from("Expected function call")))
+                if let Some(limit) = self.max_call_depth {
+                    if self.call_depth >= limit {
+                        return Err(RuntimeError::RecursionLimitExceeded {
+                            limit,
+                            trace: self.recent_locations.iter().cloned().collect(),
+                        });
+                    }
                 }
+
+                let arity = match &callee {
+                    Value::LoxFunction(function) => Some(function.arity(self)),
+                    Value::NativeFunction(function) => Some(function.arity(self)),
+                    Value::Class(_) => Some(0),
+                    _ => None,
+                };
+                if let Some(arity) = arity {
+                    if arguments_.len() != arity {
+                        return Err(RuntimeError::InvalidCall(format!("Expected {} arguments but got {} at line: {}:{}",
+                                                                     arity, arguments_.len(), paren.line, paren.col)));
+                    }
+                }
+
+                self.call_depth += 1;
+                self.call_location = SrcLocation{line: paren.line, col: paren.col};
+                let result = match callee {
+                    Value::LoxFunction(function) => function.call(self, arguments_),
+                    Value::NativeFunction(function) => function.call(self, arguments_),
+                    Value::Class(class) => Ok(Value::Instance(LoxInstance::new(class))),
+                    _ => Err(RuntimeError::InvalidCall(format!("Can only call functions and classes at line: {}:{}",
+                                                               paren.line, paren.col))),
+                };
+                self.call_depth -= 1;
+                result
             }
             // _ => Err(InterpreterError::NotImplementedError),
         }
     }
 
-    fn interpret_literal(&self, literal: Literal) -> Result<Value, RuntimeError> {
+    fn interpret_literal(&self, literal: &Literal) -> Result<Value, RuntimeError> {
         return match literal {
             Literal::False => Ok(Value::Bool(false)),
             Literal::True => Ok(Value::Bool(true)),
             Literal::Null => Ok(Value::Null),
-            Literal::String(s) => Ok(Value::String(s.clone())),
-            Literal::Number(n) => Ok(Value::Number(n)),
-            Literal::Identifier(n) => self.environment.get(n),
+            Literal::String(s) => Ok(Value::String(s.to_string())),
+            Literal::Number(n) => Ok(Value::Number(*n)),
+            Literal::Identifier(n) => self.environment.borrow().get(n.clone()),
             // _ => Err(InterpreterError::NotImplementedError),
         }
     }
 
-    fn interpret_unary(&mut self, operator: TokenType, expr: Box<Expr>) -> Result<Value, RuntimeError> {
+    fn interpret_unary(&mut self, operator: &Token, expr: &Expr) -> Result<Value, RuntimeError> {
         let right = self.interpret_expr(expr)?;
-        return match (operator, &right) {
+        return match (operator.token_type, &right) {
             (TokenType::Minus, Value::Number(n)) => Ok(Value::Number(-1.0 * n)),
             (TokenType::Bang, _) => Ok(Value::Bool(!self.is_truthy(right))),
-            _ => panic!("Unary Not implemented.")
+            _ => Err(RuntimeError::OperandMustBeNumber{line: operator.line, col: operator.col}),
         }
     }
 
-    fn interpret_logical(&mut self, left: Box<Expr>, operator: TokenType, right: Box<Expr>) -> Result<Value, RuntimeError> {
+    fn interpret_logical(&mut self, left: &Expr, operator: TokenType, right: &Expr) -> Result<Value, RuntimeError> {
         let left = self.interpret_expr(left)?;
 
         match operator {
@@ -267,34 +1099,56 @@ impl Interpreter {
         self.interpret_expr(right)
     }
 
-    fn interpret_binary(&mut self, left: Box<Expr>, operator: TokenType, right: Box<Expr>) -> Result<Value, RuntimeError> {
+    // Division follows IEEE 754 (x / 0 is +-inf, 0 / 0 is NaN) unless checked_div is enabled.
+    // Modulo by zero is always NaN, same as OpModulo in the VM.
+    fn interpret_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value, RuntimeError> {
         let left = self.interpret_expr(left)?;
         let right = self.interpret_expr(right)?;
 
-        return match (left, operator, right) {
+        if self.checked_div && operator.token_type == TokenType::Slash {
+            if let Value::Number(n) = right {
+                if n == 0.0 {
+                    return Err(RuntimeError::DivisionByZero{line: operator.line, col: operator.col});
+                }
+            }
+        }
+
+        match operator.token_type {
+            TokenType::EqualEqual => return Ok(Value::Bool(values_equal(&left, &right))),
+            TokenType::BangEqual => return Ok(Value::Bool(!values_equal(&left, &right))),
+            _ => {}
+        }
+
+        return match (left, operator.token_type, right) {
             (Value::Number(n1), TokenType::Minus, Value::Number(n2)) => Ok(Value::Number(n1 - n2)),
             (Value::Number(n1), TokenType::Plus, Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
             (Value::String(s1), TokenType::Plus, Value::String(s2)) => Ok(Value::String([s1, s2].join(""))),
+            (Value::String(s1), TokenType::Plus, v2) => Ok(Value::String(format!("{}{}", s1, v2))),
+            (v1, TokenType::Plus, Value::String(s2)) => Ok(Value::String(format!("{}{}", v1, s2))),
             (Value::Number(n1), TokenType::Slash, Value::Number(n2))  => Ok(Value::Number(n1 / n2)),
             (Value::Number(n1), TokenType::Star, Value::Number(n2))  => Ok(Value::Number(n1 * n2)),
+            (Value::Number(n1), TokenType::Percent, Value::Number(n2))  => Ok(Value::Number(n1 % n2)),
+            (Value::Number(n1), TokenType::StarStar, Value::Number(n2))  => Ok(Value::Number(n1.powf(n2))),
+            (Value::Number(n1), TokenType::Ampersand | TokenType::Pipe | TokenType::Caret |
+                TokenType::LessLess | TokenType::GreaterGreater, Value::Number(n2)) => bitwise(n1, operator, n2),
             (Value::Number(n1), TokenType::Greater, Value::Number(n2))  => Ok(Value::Bool(n1 > n2)),
             (Value::Number(n1), TokenType::GreaterEqual, Value::Number(n2))  => Ok(Value::Bool(n1 >= n2)),
             (Value::Number(n1), TokenType::Less, Value::Number(n2))  => Ok(Value::Bool(n1 < n2)),
             (Value::Number(n1), TokenType::LessEqual, Value::Number(n2))  => Ok(Value::Bool(n1 <= n2)),
-            (Value::Number(n1), TokenType::BangEqual, Value::Number(n2))  => Ok(Value::Bool(n1 != n2)),
-            (Value::Number(n1), TokenType::EqualEqual, Value::Number(n2))  => Ok(Value::Bool(n1 == n2)),
-            _ => Err(RuntimeError::BinaryOperationError),
+            (Value::String(s1), TokenType::Greater, Value::String(s2))  => Ok(Value::Bool(s1 > s2)),
+            (Value::String(s1), TokenType::GreaterEqual, Value::String(s2))  => Ok(Value::Bool(s1 >= s2)),
+            (Value::String(s1), TokenType::Less, Value::String(s2))  => Ok(Value::Bool(s1 < s2)),
+            (Value::String(s1), TokenType::LessEqual, Value::String(s2))  => Ok(Value::Bool(s1 <= s2)),
+            _ => Err(RuntimeError::BinaryOperationError{line: operator.line, col: operator.col}),
         }
     }
 
+    // Only nil and false are falsey, everything else including 0 and "" is truthy
     fn is_truthy(&self, value: Value) -> bool {
         return match value {
             Value::Bool(b) => b,
-            Value::Number(n) => n != 0.0,
-            Value::String(s) => s.len() != 0,
             Value::Null => false,
-            Value::NativeFunction(_nf) => false,
-            Value::LoxFunction(_lf) => false,
+            _ => true,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,73 @@
+// The scanner, parser, resolver and interpreter as a library, the binary in main.rs is a thin front end
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+pub mod scanner;
+pub mod token;
+pub mod expr;
+pub mod parser;
+pub mod ast_printer;
+pub mod ast_dot;
+pub mod interpreter;
+pub mod environment;
+pub mod stmt;
+pub mod vm;
+pub mod diagnostic;
+pub mod resolver;
+pub mod compiler;
+
+pub use scanner::{Scanner, ScanError};
+pub use parser::{Parser, ParseError};
+pub use resolver::{Resolver, ResolveError};
+pub use interpreter::{Interpreter, RuntimeError, Value};
+pub use compiler::CompileError;
+
+// In-memory output for an Interpreter that can still be read after handing it over
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn lines(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.borrow()).lines().map(String::from).collect()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs a whole program in a fresh interpreter, returning the lines it printed.
+// Errors come back formatted the way the binary reports them.
+pub fn run_source(src: &str) -> Result<Vec<String>, Vec<String>> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(src));
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    if !scan_errors.is_empty() {
+        return Err(scan_errors.iter().map(|e| e.to_string()).collect());
+    }
+
+    let mut parser = Parser::default();
+    let statements = parser.parse(tokens)
+        .map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<String>>())?;
+
+    let mut resolver = Resolver::default();
+    let locals = resolver.resolve(&statements);
+    if !resolver.errors().is_empty() {
+        return Err(resolver.errors().iter().map(|e| e.to_string()).collect());
+    }
+
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.resolve(locals);
+    interpreter.interpret(statements).map_err(|e| vec![format!("Runtime error: {}", e)])?;
+    Ok(output.lines())
+}
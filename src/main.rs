@@ -1,101 +1,284 @@
 // https://craftinginterpreters.com/compiling-expressions.html
 // https://github.com/tdp2110/crafting-interpreters-rs/blob/trunk/src/treewalk_interpreter.rs#L116
-mod scanner;
-mod token;
-mod expr;
-mod parser;
-mod ast_printer;
-mod interpreter;
-mod environment;
-mod stmt;
-mod vm;
-
-use std::io::{stdout, Write};
-use clap::{command, arg};
-use crate::vm::{Chunk, OpCode, SrcLocation, Vm};
-
-fn run(prog: &String, has_error: &mut bool) {
-    let mut chunk = Chunk::default();
-    let constant = chunk.add_constant(1.2);
-    chunk.write_chunk(OpCode::OpConstant as u8, SrcLocation{col: 11, line: 1});
-    chunk.write_chunk(constant, SrcLocation{col: 12, line: 1});
+use std::io::{stdout, IsTerminal, Write};
+use clap::{command, arg, value_parser};
+use crafting_rust::{ast_dot, ast_printer, compiler, diagnostic, interpreter, parser, resolver, scanner, stmt, token};
+use crafting_rust::vm::{Chunk, InterpretResult, OpCode, SrcLocation, Vm, VmValue};
+
+#[derive(Default)]
+struct Options {
+    dump_tokens: bool,
+    dump_tokens_json: bool,
+    dump_ast: bool,
+    dump_ast_dot: bool,
+    use_vm: bool,
+    dump_scopes: bool,
+    checked_div: bool,
+    trace: bool,
+    max_call_depth: Option<usize>,
+    max_steps: Option<u64>,
+}
 
-    let constant = chunk.add_constant(3.4);
-    chunk.write_chunk(OpCode::OpConstant as u8, SrcLocation{col: 13, line: 1});
-    chunk.write_chunk(constant, SrcLocation{col: 14, line: 1});
+fn make_interpreter(options: &Options) -> interpreter::Interpreter {
+    let mut interpreter = interpreter::Interpreter::default();
+    if options.dump_scopes {
+        interpreter.enable_scope_dump();
+    }
+    if options.checked_div {
+        interpreter.enable_checked_div();
+    }
+    if options.trace {
+        interpreter.enable_trace();
+    }
+    if let Some(limit) = options.max_call_depth {
+        interpreter.set_max_call_depth(limit);
+    }
+    if let Some(limit) = options.max_steps {
+        interpreter.set_step_limit(limit);
+    }
+    interpreter
+}
+
+// Hand-assembled chunk showing the bytecode VM and its disassembler at work
+fn vm_demo() {
+    let mut chunk = Chunk::default();
+    chunk.write_constant(VmValue::Double(1.2), SrcLocation{col: 11, line: 1});
+    chunk.write_constant(VmValue::Double(3.4), SrcLocation{col: 13, line: 1});
     chunk.write_chunk(OpCode::OpAdd as u8, SrcLocation{col: 15, line: 1});
 
-    let constant = chunk.add_constant(5.6);
-    chunk.write_chunk(OpCode::OpConstant as u8, SrcLocation{col: 16, line: 1});
-    chunk.write_chunk(constant, SrcLocation{col: 17, line: 1});
+    chunk.write_constant(VmValue::Double(5.6), SrcLocation{col: 16, line: 1});
+
+    // Skips the 3 byte OpConstantLong that follows, which would push 7.8
+    chunk.write_chunk(OpCode::OpJump as u8, SrcLocation{col: 20, line: 2});
+    chunk.write_chunk(0, SrcLocation{col: 20, line: 2});
+    chunk.write_chunk(4, SrcLocation{col: 20, line: 2});
+    let constant = chunk.add_constant(VmValue::Double(7.8));
+    chunk.write_chunk(OpCode::OpConstantLong as u8, SrcLocation{col: 25, line: 2});
+    for byte in [(constant >> 16) as u8, (constant >> 8) as u8, constant as u8] {
+        chunk.write_chunk(byte, SrcLocation{col: 25, line: 2});
+    }
 
     chunk.write_chunk(OpCode::OpDivide as u8, SrcLocation{col: 33, line: 3});
     chunk.write_chunk(OpCode::OpNegate as u8, SrcLocation{col: 44, line: 4});
+    chunk.write_chunk(OpCode::OpPrint as u8, SrcLocation{col: 55, line: 2});
     chunk.write_chunk(OpCode::OpReturn as u8, SrcLocation{col: 55, line: 2});
     chunk.disassemble(&"test chunk");
 
     let mut vm = Vm::default();
     vm.enable_debug();
     vm.interpret(chunk);
+}
 
-
+// Scan and parse problems are compile errors (exit code 65), failures while executing are runtime errors (70)
+fn run(prog: &String, options: &Options, interpreter: &mut interpreter::Interpreter) -> InterpretResult {
     let mut scanner = scanner::Scanner::default();
     scanner.set_source(prog);
     let mut parser = parser::Parser::default();
 
-    *has_error = scanner.had_error();
-
-    let tokens = scanner.scan_tokens();
-    for token in tokens.clone() {
-        println!("Token: {}", token.to_string());
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    for error in &scan_errors {
+        eprintln!("{}", error);
+    }
+    if options.dump_tokens {
+        for token in &tokens {
+            println!("Token: {}", token);
+        }
+    }
+    if options.dump_tokens_json {
+        println!("{}", token::to_json_array(&tokens));
+    }
+    if scanner.had_error() {
+        return InterpretResult::InterpretCompileError;
+    }
+    if options.dump_tokens || options.dump_tokens_json {
+        return InterpretResult::InterpretOk;
     }
 
-    let expr = parser.parse(tokens);
+    parser.set_source(prog);
+    let expr = match parser.parse(tokens) {
+        Ok(statements) => statements,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", parser.render_error(error));
+            }
+            return InterpretResult::InterpretCompileError;
+        }
+    };
+    if options.dump_ast_dot {
+        print!("{}", ast_dot::to_dot(&expr));
+        return InterpretResult::InterpretOk;
+    }
+    if options.dump_ast {
+        let mut printer = ast_printer::AstPrinter;
+        for statement in &expr {
+            println!("{}", printer.print(statement));
+        }
+        return InterpretResult::InterpretOk;
+    }
     // match expr {
     //     Ok(res) => {println!("Parsing successful: {}", res.to_string())},
     //     Err(err) => println!("Parse error: {}", err.to_string()),
     // }
 
-    let mut interpreter = interpreter::Interpreter::default();
-    interpreter.interpret(expr);
-    // let result = interpreter.interpret(expr);
-    //
-    // println!("{}", match result {
-    //     Ok(v) => v.to_string(),
-    //     Err(e) => e.to_string(),
-    // })
-}
+    if options.use_vm {
+        return run_vm(&expr);
+    }
 
+    let mut resolver = resolver::Resolver::default();
+    let locals = resolver.resolve(&expr);
+    for warning in resolver.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    if !resolver.errors().is_empty() {
+        for error in resolver.errors() {
+            eprintln!("{}", error);
+        }
+        return InterpretResult::InterpretCompileError;
+    }
 
-fn run_file(name: &String) {
-    if let Ok(contents) = String::from_utf8(std::fs::read(name).unwrap()) {
-        let mut has_error: bool = false;
-        run(&contents, &mut has_error);
-        if has_error {
-            std::process::exit(64);
+    interpreter.resolve(locals);
+    if let Err(e) = interpreter.interpret(expr) {
+        eprintln!("Runtime error: {}", e);
+        return InterpretResult::InterpretRuntimeError;
+    }
+    InterpretResult::InterpretOk
+}
+
+fn run_vm(statements: &Vec<stmt::Stmt>) -> InterpretResult {
+    let mut compiler = compiler::Compiler::default();
+    match compiler.compile(statements) {
+        Ok(chunks) => {
+            let mut vm = Vm::default();
+            for chunk in chunks {
+                if let InterpretResult::InterpretRuntimeError = vm.interpret(chunk) {
+                    return InterpretResult::InterpretRuntimeError;
+                }
+            }
+            InterpretResult::InterpretOk
+        }
+        Err(e) => {
+            eprintln!("Compile error: {}", e);
+            InterpretResult::InterpretCompileError
         }
+    }
+}
+
+// A name of "-" reads the whole program from stdin
+fn read_source(name: &String) -> std::io::Result<String> {
+    if name == "-" {
+        std::io::read_to_string(std::io::stdin())
     } else {
-        println!("Failed to convert to string from utf8");
+        std::fs::read_to_string(name)
     }
 }
 
+// Scans and parses only, reporting diagnostics as JSON on stdout for editor integration
+fn parse_file(name: &String) {
+    let contents = match read_source(name) {
+        Ok(c) => c,
+        Err(e) => {eprintln!("Failed to read {}: {}", name, e); std::process::exit(66)}
+    };
+    let mut scanner = scanner::Scanner::default();
+    scanner.set_source(&contents);
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    let mut parser = parser::Parser::default();
+    let parse_errors = parser.parse(tokens).err().unwrap_or_default();
+
+    let mut diagnostics: Vec<diagnostic::Diagnostic> = scan_errors.iter().map(|e| e.to_diagnostic()).collect();
+    diagnostics.extend(parse_errors.iter().map(|e| e.to_diagnostic()));
+    println!("{}", diagnostic::to_json_array(&diagnostics));
+    std::process::exit(if diagnostics.is_empty() { 0 } else { 65 });
+}
 
-fn run_prompt() {
+// Compiles without running and prints the bytecode, one chunk per top-level statement
+fn disassemble_file(name: &String) {
+    let contents = match read_source(name) {
+        Ok(c) => c,
+        Err(e) => {eprintln!("Failed to read {}: {}", name, e); std::process::exit(66)}
+    };
+    let mut scanner = scanner::Scanner::default();
+    scanner.set_source(&contents);
+    let (tokens, scan_errors) = scanner.scan_tokens();
+    for error in &scan_errors {
+        eprintln!("{}", error);
+    }
+    let mut parser = parser::Parser::default();
+    parser.set_source(&contents);
+    let statements = match parser.parse(tokens) {
+        Ok(statements) if scan_errors.is_empty() => statements,
+        Ok(_) => std::process::exit(65),
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", parser.render_error(error));
+            }
+            std::process::exit(65)
+        }
+    };
+    let mut compiler = compiler::Compiler::default();
+    match compiler.compile(&statements) {
+        Ok(chunks) => {
+            for (n, chunk) in chunks.iter().enumerate() {
+                chunk.disassemble(format!("{} #{}", name, n + 1).as_str());
+            }
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Compile error: {}", e);
+            std::process::exit(65);
+        }
+    }
+}
+
+fn run_file(name: &String, options: &Options) {
+    let contents = match read_source(name) {
+        Ok(c) => c,
+        Err(e) => {eprintln!("Failed to read {}: {}", name, e); std::process::exit(66)}
+    };
+    let mut interpreter = make_interpreter(options);
+    match run(&contents, options, &mut interpreter) {
+        InterpretResult::InterpretOk => {}
+        InterpretResult::InterpretCompileError => std::process::exit(65),
+        InterpretResult::InterpretRuntimeError => std::process::exit(70),
+    }
+}
+
+
+// True when parsing fails only because the source ends too early, like an unclosed brace
+fn is_incomplete(source: &String) -> bool {
+    let mut scanner = scanner::Scanner::default();
+    scanner.set_source(source);
+    let (tokens, _) = scanner.scan_tokens();
+    let mut parser = parser::Parser::default();
+    match parser.parse(tokens) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(|e| e.at_eof()),
+    }
+}
+
+fn run_prompt(options: &Options) {
     let mut line: String = Default::default();
     let mut bytes: usize;
-    let mut has_error: bool = false;
+    let mut interpreter = make_interpreter(options);
+    interpreter.enable_repl_mode();
 
     loop {
-        print!("> ");
+        // Lines accumulate until the statement is complete, a blank line drops the pending input
+        print!("{}", if line.is_empty() { "> " } else { ". " });
         let _ = stdout().flush();
+        let start = line.len();
         bytes = std::io::stdin().read_line(&mut line).unwrap();
         if bytes == 0 {
             break;
         }
-        run(&line, &mut has_error);
-        if has_error {
-            has_error = false;
+        if start > 0 && line[start..].trim().is_empty() {
+            line.clear();
+            continue;
         }
+        if is_incomplete(&line) {
+            continue;
+        }
+        // Errors are already reported, the prompt just carries on
+        run(&line, options, &mut interpreter);
         line.clear();
     }
 }
@@ -103,13 +286,55 @@ fn run_prompt() {
 
 fn main() {
     let matches = command!()
-        .arg(arg!([name] "Optional file name to process"))
+        .arg(arg!([name] "Optional file name to process, - reads the program from stdin"))
+        .arg(arg!(--tokens "Print the scanned tokens instead of running the program"))
+        .arg(arg!(--"tokens-json" "Print the scanned tokens as a JSON array instead of running the program"))
+        .arg(arg!(--ast "Print the parsed statements instead of running the program"))
+        .arg(arg!(--"ast-dot" "Print the parsed statements as a Graphviz digraph instead of running the program"))
+        .arg(arg!(--vm "Compile to bytecode and run it on the VM instead of the tree-walker"))
+        .arg(arg!(--"vm-demo" "Run a hand-assembled chunk on the bytecode VM"))
+        .arg(arg!(--"parse-only" "Only scan and parse, printing diagnostics as JSON").requires("name"))
+        .arg(arg!(--disassemble "Compile to bytecode and print it instead of running").requires("name"))
+        .arg(arg!(--repl "Run the interactive prompt even when stdin is not a terminal").conflicts_with("name"))
+        .arg(arg!(--scopes "Print the environment chain when leaving each block"))
+        .arg(arg!(--"checked-div" "Make division by zero a runtime error instead of inf/NaN"))
+        .arg(arg!(--trace "Log each executed statement and expression result to stderr"))
+        .arg(arg!(--"max-depth" <N> "Fail with a trace of recent locations after N nested calls")
+            .value_parser(value_parser!(usize)))
+        .arg(arg!(--"max-steps" <N> "Fail with a trace of recent locations after executing N statements")
+            .value_parser(value_parser!(u64)))
         .get_matches();
 
+    let options = Options {
+        dump_tokens: matches.get_flag("tokens"),
+        dump_tokens_json: matches.get_flag("tokens-json"),
+        dump_ast: matches.get_flag("ast"),
+        dump_ast_dot: matches.get_flag("ast-dot"),
+        use_vm: matches.get_flag("vm"),
+        dump_scopes: matches.get_flag("scopes"),
+        checked_div: matches.get_flag("checked-div"),
+        trace: matches.get_flag("trace"),
+        max_call_depth: matches.get_one::<usize>("max-depth").copied(),
+        max_steps: matches.get_one::<u64>("max-steps").copied(),
+    };
+
+    if matches.get_flag("vm-demo") {
+        vm_demo();
+        std::process::exit(0);
+    }
+
     if let Some(n) = matches.get_one::<String>("name") {
-        run_file(&n);
+        if matches.get_flag("parse-only") {
+            parse_file(n);
+        }
+        if matches.get_flag("disassemble") {
+            disassemble_file(n);
+        }
+        run_file(&n, &options);
+    } else if matches.get_flag("repl") || std::io::stdin().is_terminal() {
+        run_prompt(&options);
     } else {
-        run_prompt();
+        run_file(&String::from("-"), &options);
     }
     std::process::exit(0);
 }
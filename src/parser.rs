@@ -1,5 +1,6 @@
 use std::vec;
-use crate::expr::Expr;
+use crate::diagnostic::{snippet, Diagnostic};
+use crate::expr::{next_expr_id, Expr};
 use crate::stmt::Stmt;
 use crate::token::{Literal, Token, TokenType};
 
@@ -18,6 +19,35 @@ pub enum ParseError {
         found: TokenType,
         line: usize,
         col: usize,
+    },
+    InvalidStatement {
+        message: String,
+        line: usize,
+        col: usize,
+    },
+}
+
+impl ParseError {
+    // The input ended before the construct was complete, more input could fix it
+    pub fn at_eof(&self) -> bool {
+        match self {
+            ParseError::ParseError{found, ..} => *found == TokenType::EOF,
+            ParseError::ExpectedExpression{found, ..} => *found == TokenType::EOF,
+            ParseError::InvalidStatement{..} => false,
+        }
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            ParseError::ParseError{line, col, ..} => (*line, *col),
+            ParseError::ExpectedExpression{line, col, ..} => (*line, *col),
+            ParseError::InvalidStatement{line, col, ..} => (*line, *col),
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let (line, col) = self.position();
+        Diagnostic::error(line, col, self.to_string())
     }
 }
 
@@ -27,58 +57,137 @@ impl std::fmt::Display for ParseError {
             ParseError::ParseError{expected, found ,message, line, col} =>
                 write!(f, "Unexpected token {:?}, expected {:?}: {} at line: {}:{}.", found, expected, message, line, col),
             ParseError::ExpectedExpression{expected, found, line, col} =>
-                write!(f, "Unexpected expression {}. Expected {:?} at line: {}:{}.", found, expected, line, col)
+                write!(f, "Unexpected expression {}. Expected {:?} at line: {}:{}.", found, expected, line, col),
+            ParseError::InvalidStatement{message, line, col} =>
+                write!(f, "{} at line: {}:{}.", message, line, col),
         }
     }
 }
 
 /*
-expression     → equality ;
-equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+expression     → assignment ;
+assignment     → ( call "." )? IDENTIFIER "=" assignment
+               | call "[" expression "]" "=" assignment
+               | ternary ;
+ternary        → logic_or ( "?" expression ":" ternary )? ;
+logic_or       → logic_and ( "or" logic_and )* ;
+logic_and      → equality ( "and" equality )* ;
+equality       → bit_or ( ( "!=" | "==" ) bit_or )* ;
+bit_or         → bit_xor ( "|" bit_xor )* ;
+bit_xor        → bit_and ( "^" bit_and )* ;
+bit_and        → comparison ( "&" comparison )* ;
+comparison     → shift ( ( ">" | ">=" | "<" | "<=" ) shift )* ;
+shift          → term ( ( "<<" | ">>" ) term )* ;
 term           → factor ( ( "-" | "+" ) factor )* ;
-factor         → unary ( ( "/" | "*" ) unary )* ;
-unary          → ( "!" | "-" ) unary
-               | primary ;
-primary        → NUMBER | STRING | "true" | "false" | "nil"
-               | "(" expression ")" ;
+factor         → unary ( ( "/" | "*" | "%" ) unary )* ;
+unary          → ( "!" | "-" | "++" | "--" ) unary
+               | power ;
+power          → call ( "**" unary )? ;
+call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" | "++" | "--" )* ;
+arguments      → expression ( "," expression )* ;
+primary        → NUMBER | STRING | "true" | "false" | "nil" | "this" | IDENTIFIER
+               | "super" "." IDENTIFIER
+               | "(" expression ")"
+               | "[" arguments? "]"
+               | "{" ( expression ":" expression ( "," expression ":" expression )* )? "}"
+               | "fun" "(" parameters? ")" block ;
  */
 
 
+// Deeper programs would overflow the native stack, here or in the passes walking the tree later
+const MAX_NESTING: usize = 128;
+
 #[derive(Default)]
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
-    statements: Vec<Stmt>
+    statements: Vec<Stmt>,
+    errors: Vec<ParseError>,
+    // Number of loops enclosing the statement being parsed, reset inside function bodies
+    loop_depth: usize,
+    // Statements and unary operands currently being parsed inside each other, see nested
+    depth: usize,
+    // Source text split the way the scanner counts lines, only used to render errors
+    lines: Vec<String>,
 }
 
 
 impl Parser {
-    pub fn parse(&mut self, tokens: Vec<Token>) -> Vec<Stmt> {
+    pub fn set_source(&mut self, source: &str) {
+        self.lines = source.replace("\r\n", "\n").split(['\n', '\r']).map(String::from).collect();
+    }
+
+    // The error followed by its source line with a caret under the column, or just the error
+    // when no source was set
+    pub fn render_error(&self, error: &ParseError) -> String {
+        let (line, col) = error.position();
+        match self.lines.get(line.wrapping_sub(1)) {
+            Some(text) => format!("{}\n{}", error, snippet(text, line, col)),
+            None => error.to_string(),
+        }
+    }
+
+    // Recovers after each error at the next statement boundary, so every independent error is reported
+    pub fn parse(&mut self, tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<ParseError>> {
         self.tokens = tokens;
         self.current = 0;
         self.statements = vec![];
+        self.errors = vec![];
+        self.loop_depth = 0;
+        self.depth = 0;
 
         while !self.is_at_end() {
              match self.declaration_or_stmt() {
                  Ok(res) => self.statements.push(res),
-                 Err(err) => {self.synchronize(); eprintln!("{}", err)},
+                 Err(err) => {self.synchronize(); self.errors.push(err)},
              }
         }
 
-        self.statements.clone()
+        if self.errors.is_empty() {
+            Ok(std::mem::take(&mut self.statements))
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
     fn declaration_or_stmt(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_(vec![TokenType::Fun]) {
+        self.nested(Parser::declaration)
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.match_(vec![TokenType::Class]) {
+            return self.class_declaration();
+        }
+        // A `fun` not followed by a name starts a lambda expression statement
+        if self.check(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+            self.advance();
             return self.fun_declaration(String::from("function"));
         }
         if self.match_(vec![TokenType::Var]) {
             return self.var_declaration();
         }
+        if self.match_(vec![TokenType::Const]) {
+            return self.const_declaration();
+        }
         self.statement()
     }
 
+    // Every recursive path through the grammar passes a statement, a unary operand or the right
+    // operand of a right-associative operator, so counting those bounds the recursion
+    fn nested<T>(&mut self, parse: fn(&mut Parser) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::InvalidStatement {
+                message: String::from("Too much nesting"),
+                line: self.peek().line,
+                col: self.peek().col,
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     fn make_error(&self, expected: TokenType, message: String) -> ParseError {
         ParseError::ParseError {
             expected,
@@ -89,9 +198,31 @@ impl Parser {
         }
     }
 
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume_(TokenType::Identifier, String::from("Expect class name."))?;
+        let mut superclass: Option<Expr> = None;
+        if self.match_(vec![TokenType::Less]) {
+            let superclass_name = self.consume_(TokenType::Identifier, String::from("Expect superclass name."))?;
+            superclass = Some(Expr::Variable(superclass_name, next_expr_id()));
+        }
+        self.consume_(TokenType::LeftBrace, String::from("Expect '{' before class body."))?;
+        let mut methods: Vec<Stmt> = vec![];
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.fun_declaration(String::from("method"))?);
+        }
+        self.consume_(TokenType::RightBrace, String::from("Expect '}' after class body."))?;
+        Ok(Stmt::Class(name, superclass, methods))
+    }
+
     fn fun_declaration(&mut self, kind: String) -> Result<Stmt, ParseError> {
         let name = self.consume_(TokenType::Identifier,
                                        String::from(format!("Expecting {} name.", kind).as_str()))?;
+        let (parameters, body) = self.function_body(kind)?;
+        Ok(Stmt::Function(name, parameters, body))
+    }
+
+    // Parameter list and body, shared by function declarations, methods and lambdas
+    fn function_body(&mut self, kind: String) -> Result<(Vec<Token>, Vec<Stmt>), ParseError> {
         self.consume_(TokenType::LeftParen, String::from(format!("Expect '(' after {} name.", kind).as_str()))?;
         let mut parameters: Vec<Token> = vec![];
         if !self.check(TokenType::RightParen) {
@@ -108,8 +239,10 @@ impl Parser {
         }
         self.consume_(TokenType::RightParen, String::from("Expect ')' after parameters."))?;
         self.consume_(TokenType::LeftBrace, String::from(format!("Expect '{{' before {} body.", kind).as_str()))?;
-        let body = self.block()?;
-        Ok(Stmt::Function(name, parameters, body))
+        let enclosing_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = enclosing_loop_depth;
+        Ok((parameters, body?))
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
@@ -128,52 +261,115 @@ impl Parser {
         if self.match_(vec![TokenType::If]) {
             return self.if_statement();
         };
+        if self.match_(vec![TokenType::Return]) {
+            return self.return_statement();
+        };
+        if self.match_(vec![TokenType::Break, TokenType::Continue]) {
+            return self.loop_jump_statement();
+        };
+        if self.check(TokenType::Else) {
+            let keyword = self.peek().clone();
+            return Err(ParseError::InvalidStatement {
+                message: String::from("Found 'else' without a matching 'if'"),
+                line: keyword.line,
+                col: keyword.col,
+            });
+        }
         self.expression_statement()
     }
 
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let mut value: Option<Expr> = None;
+        if !self.check(TokenType::Semicolon) {
+            value = Some(self.expression()?);
+        }
+        self.consume_(TokenType::Semicolon, String::from("Expect ';' after return value."))?;
+        Ok(Stmt::Return(keyword, value))
+    }
+
+    fn loop_jump_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        self.consume_(TokenType::Semicolon, format!("Expect ';' after '{}'.", keyword.lexeme))?;
+        // The statement itself is well formed, so parsing carries on instead of synchronizing
+        // somewhere inside the enclosing block
+        if self.loop_depth == 0 {
+            self.errors.push(ParseError::InvalidStatement {
+                message: format!("Can't use '{}' outside of a loop", keyword.lexeme),
+                line: keyword.line,
+                col: keyword.col,
+            });
+        }
+        if keyword.token_type == TokenType::Break {
+            Ok(Stmt::Break)
+        } else {
+            Ok(Stmt::Continue)
+        }
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    // for (item in array) body
+    fn for_each_statement(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.advance().clone();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume_(TokenType::RightParen, String::from("Expect ')' after for-in clause."))?;
+        let body = self.loop_body()?;
+        Ok(Stmt::ForEach(name, iterable, Box::new(body)))
+    }
+
     fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
         self.consume_(TokenType::LeftParen, String::from("Expect '(' after 'while'."))?;
         let condition = self.expression()?;
         self.consume_(TokenType::RightParen, String::from("Expect ')' after condition."))?;
-        let body = self.statement()?;
-        Ok(Stmt::While(condition, Box::new(body)))
+        let body = self.loop_body()?;
+        Ok(Stmt::While(keyword, condition, Box::new(body), None))
     }
 
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
         self.consume_(TokenType::LeftParen, String::from("Expect '(' after 'for'."))?;
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+            return self.for_each_statement();
+        }
         let initializer: Option<Stmt>;
         if self.match_(vec![TokenType::Semicolon]) {
             initializer = None;
         } else if self.match_(vec![TokenType::Var]) {
-            initializer = self.var_declaration().ok();
+            initializer = Some(self.var_declaration()?);
         } else {
-            initializer = self.expression_statement().ok();
+            initializer = Some(self.expression_statement()?);
         }
 
         let mut condition: Option<Expr> = None;
         if !self.check(TokenType::Semicolon) {
-            condition = self.expression().ok();
+            condition = Some(self.expression()?);
         }
 
         self.consume_(TokenType::Semicolon, String::from("Expect ';' after loop condition."))?;
 
         let mut increment: Option<Expr> = None;
         if !self.check(TokenType::RightParen) {
-            increment = self.expression().ok();
+            increment = Some(self.expression()?);
         }
         self.consume_(TokenType::RightParen, String::from("Expect ')' after for clauses."))?;
 
-        let mut body = self.statement()?;
-
-        if increment.is_some() {
-            body = Stmt::Block(vec![body, Stmt::Expression(increment.unwrap())])
-        }
+        let mut body = self.loop_body()?;
 
         if !condition.is_some() {
-            condition = Some(Expr::Literal(Literal::True));
+            condition = Some(Expr::Literal(Literal::True, keyword.clone()));
         }
 
-        body = Stmt::While(condition.unwrap(), Box::new(body));
+        // The increment stays separate from the body so that `continue` still runs it.
+        // Keeping the `for` keyword lets diagnostics point at the loop the user wrote.
+        body = Stmt::While(keyword, condition.unwrap(), Box::new(body), increment);
 
         if initializer.is_some() {
             body = Stmt::Block(vec![initializer.unwrap(), body]);
@@ -185,6 +381,7 @@ impl Parser {
     fn if_statement(&mut self) -> Result<Stmt, ParseError> {
         self.consume_(TokenType::LeftParen, String::from("Expect '(' after 'if'."))?;
         let condition = self.expression()?;
+        self.consume_(TokenType::RightParen, String::from("Expect ')' after if condition."))?;
         let then = Box::new(self.statement()?);
         let mut else_branch: Option<Box<Stmt>> = None;
         if self.match_(vec![TokenType::Else]) {
@@ -225,14 +422,20 @@ impl Parser {
         let name = self.consume_(TokenType::Identifier, String::from("Expected variable name"))?;
         let mut initializer: Option<Expr> = None;
         if self.match_(vec![TokenType::Equal]) {
-            initializer = match self.expression() {
-                Ok(e) => Some(e),
-                Err(_) => None,
-            };
+            initializer = Some(self.expression()?);
         }
 
         self.consume_(TokenType::Semicolon, String::from("Expected ';' after variable declaration"))?;
-        Ok(Stmt::VarDeclaration(name, initializer))
+        Ok(Stmt::VarDeclaration(name, initializer, false))
+    }
+
+    // Like var, but the value can't be assigned to later, so it has to be given here
+    fn const_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume_(TokenType::Identifier, String::from("Expected constant name"))?;
+        self.consume_(TokenType::Equal, String::from("Constant must be initialized"))?;
+        let initializer = self.expression()?;
+        self.consume_(TokenType::Semicolon, String::from("Expected ';' after constant declaration"))?;
+        Ok(Stmt::VarDeclaration(name, Some(initializer), true))
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
@@ -240,16 +443,22 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.or()?;
+        let expr = self.ternary()?;
 
         if self.match_(vec![TokenType::Equal]) {
-            let right = self.assignment()?;
+            let right = self.nested(Parser::assignment)?;
 
-            if let Expr::Variable(l) = &expr {
+            if let Expr::Variable(l, _) = &expr {
                 if TokenType::Identifier == l.token_type {
-                    return Ok(Expr::Assign(l.clone(), Box::new(right)));
+                    return Ok(Expr::Assign(l.clone(), Box::new(right), next_expr_id()));
                 }
             }
+            if let Expr::Get(object, name) = expr {
+                return Ok(Expr::Set(object, name, Box::new(right)));
+            }
+            if let Expr::Index(object, bracket, index) = expr {
+                return Ok(Expr::SetIndex(object, bracket, index, Box::new(right)));
+            }
             return Err(ParseError::ParseError {
                 expected: TokenType::Var,
                 found: TokenType::Nil,
@@ -262,10 +471,25 @@ impl Parser {
         Ok(expr)
     }
 
+    // Right-associative: a ? b : c ? d : e is a ? b : (c ? d : e)
+    fn ternary(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.or()?;
+
+        if self.match_(vec![TokenType::Question]) {
+            let question = self.previous().clone();
+            let then = self.expression()?;
+            self.consume_(TokenType::Colon, String::from("Expect ':' after then branch of conditional expression."))?;
+            let otherwise = self.nested(Parser::ternary)?;
+            return Ok(Expr::Ternary(Box::new(expr), question, Box::new(then), Box::new(otherwise)));
+        }
+
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.and()?;
         while self.match_(vec![TokenType::Or]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.and()?;
             expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
         }
@@ -276,7 +500,7 @@ impl Parser {
     fn and(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.equality()?;
         while self.match_(vec![TokenType::And]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.equality()?;
             expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
         }
@@ -285,9 +509,43 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_or()?;
+        while self.match_(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
+            let operator = self.previous().clone();
+            let right = Box::new(self.bit_or()?);
+            expr = Expr::Binary(Box::new(expr), operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    // The bitwise operators bind looser than comparison, `|` loosest, as in C
+    fn bit_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_xor()?;
+        while self.match_(vec![TokenType::Pipe]) {
+            let operator = self.previous().clone();
+            let right = Box::new(self.bit_xor()?);
+            expr = Expr::Binary(Box::new(expr), operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_and()?;
+        while self.match_(vec![TokenType::Caret]) {
+            let operator = self.previous().clone();
+            let right = Box::new(self.bit_and()?);
+            expr = Expr::Binary(Box::new(expr), operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.comparison()?;
-        if self.match_(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous();
+        while self.match_(vec![TokenType::Ampersand]) {
+            let operator = self.previous().clone();
             let right = Box::new(self.comparison()?);
             expr = Expr::Binary(Box::new(expr), operator, right);
         }
@@ -296,10 +554,22 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.term()?;
-        if self.match_(vec![TokenType::Greater, TokenType::GreaterEqual,
+        let mut expr = self.shift()?;
+        while self.match_(vec![TokenType::Greater, TokenType::GreaterEqual,
                             TokenType::Less, TokenType::LessEqual]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
+            let right = Box::new(self.shift()?);
+            expr = Expr::Binary(Box::new(expr), operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    // Shifts sit between comparison and term, so `1 << 2 + 1` shifts by 3
+    fn shift(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+        while self.match_(vec![TokenType::LessLess, TokenType::GreaterGreater]) {
+            let operator = self.previous().clone();
             let right = Box::new(self.term()?);
             expr = Expr::Binary(Box::new(expr), operator, right);
         }
@@ -310,7 +580,7 @@ impl Parser {
     fn term(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.factor()?;
         while self.match_(vec![TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = Box::new(self.factor()?);
             expr = Expr::Binary(Box::new(expr), operator, right);
         }
@@ -320,8 +590,8 @@ impl Parser {
 
     fn factor(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.unary()?;
-        while self.match_(vec![TokenType::Star, TokenType::Slash]) {
-            let operator = self.previous();
+        while self.match_(vec![TokenType::Star, TokenType::Slash, TokenType::Percent]) {
+            let operator = self.previous().clone();
             let right = Box::new(self.unary()?);
             expr = Expr::Binary(Box::new(expr), operator, right);
         }
@@ -330,20 +600,54 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Parser::prefix)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, ParseError> {
         if self.match_(vec![TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = Box::new(self.unary()?);
             return Ok(Expr::Unary(operator, right));
         }
+        if self.match_(vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().clone();
+            let target = self.unary()?;
+            return self.increment(target, operator);
+        }
 
-        self.call()
+        self.power()
     }
 
+    // Binds tighter than unary minus on its left: -2 ** 2 is -(2 ** 2). Right-associative, and the
+    // exponent may itself be negated: 2 ** -1
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.call()?;
+        if self.match_(vec![TokenType::StarStar]) {
+            let operator = self.previous().clone();
+            let right = Box::new(self.unary()?);
+            return Ok(Expr::Binary(Box::new(expr), operator, right));
+        }
+
+        Ok(expr)
+    }
+
+    // Calls, property accesses and indexing chain left to right: a.b[0]() is ((a.b)[0])()
     fn call(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.primary()?;
         loop {
             if self.match_(vec![TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_(vec![TokenType::Dot]) {
+                let name = self.consume_(TokenType::Identifier, String::from("Expect property name after '.'."))?;
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.match_(vec![TokenType::LeftBracket]) {
+                let bracket = self.previous().clone();
+                let index = self.expression()?;
+                self.consume_(TokenType::RightBracket, String::from("Expect ']' after index."))?;
+                expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+            } else if self.match_(vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+                let operator = self.previous().clone();
+                expr = self.increment(expr, operator)?;
             } else {
                 break;
             }
@@ -352,6 +656,27 @@ impl Parser {
         Ok(expr)
     }
 
+    // `i++`, `++i`, `i--` and `--i` all mean `i = i + 1` or `i = i - 1`, so prefix and postfix
+    // forms alike evaluate to the updated value
+    fn increment(&mut self, target: Expr, operator: Token) -> Result<Expr, ParseError> {
+        let Expr::Variable(name, _) = target else {
+            return Err(ParseError::InvalidStatement {
+                message: format!("Operand of '{}' must be a variable", operator.lexeme),
+                line: operator.line,
+                col: operator.col,
+            });
+        };
+        let (token_type, lexeme) = match operator.token_type {
+            TokenType::PlusPlus => (TokenType::Plus, "+"),
+            _ => (TokenType::Minus, "-"),
+        };
+        let binary = Token::new(token_type, String::from(lexeme), Literal::Null, operator.line, operator.col);
+        let one = Token::new(TokenType::Number, String::from("1"), Literal::Number(1.0), operator.line, operator.col);
+        let value = Expr::Binary(Box::new(Expr::Variable(name.clone(), next_expr_id())), binary,
+                                 Box::new(Expr::Literal(Literal::Number(1.0), one)));
+        Ok(Expr::Assign(name, Box::new(value), next_expr_id()))
+    }
+
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
         let mut arguments: Vec<Expr> = vec![];
         if !self.check(TokenType::RightParen) {
@@ -381,21 +706,62 @@ impl Parser {
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
         if self.match_(vec![TokenType::False]) {
-            return Ok(Expr::Literal(Literal::False));
+            return Ok(Expr::Literal(Literal::False, self.previous().clone()));
         } else if self.match_(vec![TokenType::True]) {
-            return Ok(Expr::Literal(Literal::True));
+            return Ok(Expr::Literal(Literal::True, self.previous().clone()));
         } else if self.match_(vec![TokenType::Nil]) {
-            return Ok(Expr::Literal(Literal::Null));
+            return Ok(Expr::Literal(Literal::Null, self.previous().clone()));
         } else if self.match_(vec![TokenType::Number, TokenType::String]) {
-            return Ok(Expr::Literal(self.previous().literal));
+            let token = self.previous().clone();
+            return Ok(Expr::Literal(token.literal.clone(), token));
+        } else if self.match_(vec![TokenType::Super]) {
+            let keyword = self.previous().clone();
+            self.consume_(TokenType::Dot, String::from("Expect '.' after 'super'."))?;
+            let method = self.consume_(TokenType::Identifier, String::from("Expect superclass method name."))?;
+            return Ok(Expr::Super(keyword, method, next_expr_id()));
+        } else if self.match_(vec![TokenType::Fun]) {
+            let keyword = self.previous().clone();
+            let (parameters, body) = self.function_body(String::from("fun"))?;
+            return Ok(Expr::Lambda(keyword, parameters, body));
+        } else if self.match_(vec![TokenType::This]) {
+            return Ok(Expr::This(self.previous().clone(), next_expr_id()));
         } else if self.match_(vec![TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous()));
+            return Ok(Expr::Variable(self.previous().clone(), next_expr_id()));
+        } else if self.match_(vec![TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = vec![];
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.match_(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume_(TokenType::RightBracket, String::from("Expect ']' after array elements."))?;
+            return Ok(Expr::Array(bracket, elements));
+        } else if self.match_(vec![TokenType::LeftBrace]) {
+            // A statement starting with '{' is a block, so here it can only be a map
+            let brace = self.previous().clone();
+            let mut entries = vec![];
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume_(TokenType::Colon, String::from("Expect ':' after map key."))?;
+                    entries.push((key, self.expression()?));
+                    if !self.match_(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume_(TokenType::RightBrace, String::from("Expect '}' after map entries."))?;
+            return Ok(Expr::Map(brace, entries));
         } else if self.match_(vec![TokenType::LeftParen]) {
+            let paren = self.previous().clone();
             let expr: Box<Expr> = Box::new(self.expression()?);
             self.consume_(TokenType::RightParen, String::from("Expect ')' after expression."))?;
-            return Ok(Expr::Grouping(expr));
+            return Ok(Expr::Grouping(paren, expr));
         } else {
-            eprintln!("Failed in Parser::primary()");
             let last_token = self.peek();
             Err(ParseError::ExpectedExpression {
                 expected: vec![TokenType::False, TokenType::True, TokenType::Nil, TokenType::Number,
@@ -432,7 +798,14 @@ impl Parser {
         }
     }
 
-    fn advance(&mut self) -> Token {
+    fn check_next(&self, token_type: TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.token_type == token_type,
+            None => false,
+        }
+    }
+
+    fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
         }
@@ -443,19 +816,19 @@ impl Parser {
         self.peek().token_type == TokenType::EOF
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    // Borrowed, a token is only cloned when an Expr or Stmt keeps it
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
     }
 
     fn consume_(&mut self, token_type: TokenType, message: String) -> Result<Token, ParseError> {
         return if self.check(token_type) {
-            Ok(self.advance())
+            Ok(self.advance().clone())
         } else {
-            eprintln!("Parse Error: {}", message);
             let last_token = self.peek();
             Err(ParseError::ParseError {
                 expected: token_type,
@@ -476,16 +849,9 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                TokenType::Class => {}
-                TokenType::Var => {},
-                TokenType::Fun => {}
-                TokenType::For => {}
-                TokenType::If => {}
-                TokenType::While => {}
-                TokenType::Print => {},
-                TokenType::Return => {return},
-                TokenType::Semicolon => {},
-                _ => {eprintln!("Unexpected token_type in Parser::synchronize(): {}", self.peek().token_type)}
+                TokenType::Class | TokenType::Var | TokenType::Const | TokenType::Fun | TokenType::For | TokenType::If |
+                TokenType::While | TokenType::Print | TokenType::Return => return,
+                _ => {}
             }
             self.advance();
         }
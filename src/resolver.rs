@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use crate::expr::{Expr, ExprId};
+use crate::stmt::Stmt;
+use crate::token::Token;
+
+
+#[derive(Debug)]
+pub struct ResolveError {
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at line: {}:{}.", self.message, self.line, self.col)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// Statically binds every local variable use to the scope it was declared in. Depths are counted
+// from the innermost scope outwards; variables not found in any scope are globals.
+pub struct Resolver {
+    // Per scope: variable name -> whether its initializer has finished
+    scopes: Vec<HashMap<String, bool>>,
+    // Per scope: `var` declarations not read yet, reported as unused when the scope ends.
+    // Globals, parameters and loop variables are not tracked.
+    unread: Vec<HashMap<String, Token>>,
+    locals: HashMap<ExprId, usize>,
+    errors: Vec<ResolveError>,
+    warnings: Vec<ResolveError>,
+    current_function: FunctionType,
+    current_class: ClassType,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver {
+            scopes: vec![],
+            unread: vec![],
+            locals: HashMap::new(),
+            errors: vec![],
+            warnings: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+        }
+    }
+}
+
+impl Resolver {
+    pub fn resolve(&mut self, statements: &Vec<Stmt>) -> HashMap<ExprId, usize> {
+        self.scopes = vec![];
+        self.unread = vec![];
+        self.locals = HashMap::new();
+        self.errors = vec![];
+        self.warnings = vec![];
+        for statement in statements {
+            self.resolve_stmt(statement);
+        }
+        self.locals.clone()
+    }
+
+    pub fn errors(&self) -> &Vec<ResolveError> {
+        &self.errors
+    }
+
+    // Not fatal, the program still runs
+    pub fn warnings(&self) -> &Vec<ResolveError> {
+        &self.warnings
+    }
+
+    fn resolve_stmt(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                for s in statements {
+                    self.resolve_stmt(s);
+                }
+                self.end_scope();
+            }
+            Stmt::VarDeclaration(name, initializer, _) => {
+                self.declare(name);
+                if let Some(unread) = self.unread.last_mut() {
+                    unread.insert(name.lexeme.clone(), name.clone());
+                }
+                if let Some(e) = initializer {
+                    self.resolve_expr(e);
+                }
+                self.define(name);
+            }
+            Stmt::Function(name, params, body) => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, body, FunctionType::Function);
+            }
+            Stmt::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable(superclass_name, _) = superclass {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+
+                    // Methods of a subclass close over a scope holding `super`
+                    self.begin_scope();
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(String::from("super"), true);
+                    }
+                }
+
+                // Bound methods get an extra scope holding `this`, see LoxFunction::bind
+                self.begin_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(String::from("this"), true);
+                }
+                for method in methods {
+                    if let Stmt::Function(_, params, body) = method {
+                        self.resolve_function(params, body, FunctionType::Method);
+                    }
+                }
+                self.end_scope();
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Stmt::Expression(e) => self.resolve_expr(e),
+            Stmt::Print(e) => self.resolve_expr(e),
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While(_keyword, condition, body, increment) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+                if let Some(e) = increment {
+                    self.resolve_expr(e);
+                }
+            }
+            Stmt::ForEach(name, iterable, body) => {
+                self.resolve_expr(iterable);
+                // The loop variable gets its own scope, like the environment of each iteration
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Break => {}
+            Stmt::Continue => {}
+            Stmt::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(e) = value {
+                    self.resolve_expr(e);
+                }
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable(name, id) => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(&name.lexeme) == Some(&false) {
+                        self.error(name, "Can't read local variable in its own initializer.");
+                    }
+                }
+                self.resolve_local(*id, name);
+                self.mark_read(name);
+            }
+            Expr::Assign(name, value, id) => {
+                self.resolve_expr(value);
+                self.resolve_local(*id, name);
+            }
+            Expr::Binary(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Logical(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Ternary(condition, _, then, otherwise) => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then);
+                self.resolve_expr(otherwise);
+            }
+            Expr::Call(callee, _, arguments) => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Get(object, _) => self.resolve_expr(object),
+            Expr::Set(object, _, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::This(keyword, id) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.resolve_local(*id, keyword);
+            }
+            Expr::Super(keyword, _, id) => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                    ClassType::Subclass => self.resolve_local(*id, keyword),
+                }
+            }
+            Expr::Array(_, elements) => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Index(object, _, index) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex(object, _, index, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::Lambda(_, params, body) => self.resolve_function(params, body, FunctionType::Function),
+            Expr::Grouping(_, e) => self.resolve_expr(e),
+            Expr::Unary(_, e) => self.resolve_expr(e),
+            Expr::Literal(_, _) => {}
+        }
+    }
+
+    fn resolve_function(&mut self, params: &Vec<Token>, body: &Vec<Stmt>, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        // Parameters and the body share one scope, matching the call environment
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        for s in body {
+            self.resolve_stmt(s);
+        }
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_local(&mut self, id: ExprId, name: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name.lexeme) {
+                self.locals.insert(id, depth);
+                return;
+            }
+        }
+    }
+
+    // Assignments don't count, only reads do
+    fn mark_read(&mut self, name: &Token) {
+        for (scope, unread) in self.scopes.iter().zip(self.unread.iter_mut()).rev() {
+            if scope.contains_key(&name.lexeme) {
+                unread.remove(&name.lexeme);
+                return;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.unread.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        if let Some(unread) = self.unread.pop() {
+            let mut names: Vec<Token> = unread.into_values().collect();
+            names.sort_by_key(|name| (name.line, name.col));
+            for name in names {
+                let message = format!("Unused variable '{}'", name.lexeme);
+                self.warnings.push(ResolveError { message, line: name.line, col: name.col });
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let already_declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexeme.clone(), false).is_some(),
+            None => false,
+        };
+        if already_declared {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError { message: String::from(message), line: token.line, col: token.col });
+    }
+}
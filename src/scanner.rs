@@ -1,17 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::diagnostic::Diagnostic;
 use crate::token::Literal;
 use crate::token::TokenType;
 use crate::token::Token;
 
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl ScanError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.line, self.col, self.message.clone())
+    }
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at line: {}:{}.", self.message, self.line, self.col)
+    }
+}
+
 pub struct Scanner {
-    source: Vec<u8>,
+    // Chars rather than bytes, so multi-byte characters keep columns and slices intact
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
     last_line_start: usize,
-    col: usize,
-    had_error: bool,
+    start_line: usize,
+    start_col: usize,
+    errors: Vec<ScanError>,
+    strings: HashSet<Rc<str>>,
     keywords: HashMap<String, TokenType>,
 }
 
@@ -25,16 +49,22 @@ impl Default for Scanner {
             current: 0,
             line: 1,
             last_line_start: 0,
-            col: 0,
-            had_error: false,
+            start_line: 1,
+            start_col: 1,
+            errors: vec![],
+            strings: HashSet::new(),
             keywords: HashMap::from([
                 (String::from("and"), TokenType::And),
+                (String::from("break"), TokenType::Break),
                 (String::from("class"), TokenType::Class),
+                (String::from("const"), TokenType::Const),
+                (String::from("continue"), TokenType::Continue),
                 (String::from("else"), TokenType::Else),
                 (String::from("false"), TokenType::False),
                 (String::from("for"), TokenType::For),
                 (String::from("fun"), TokenType::Fun),
                 (String::from("if"), TokenType::If),
+                (String::from("in"), TokenType::In),
                 (String::from("nil"), TokenType::Nil),
                 (String::from("or"), TokenType::Or),
                 (String::from("print"), TokenType::Print),
@@ -51,22 +81,29 @@ impl Default for Scanner {
 
 impl Scanner {
     pub fn set_source(&mut self, source: &String) {
-        self.source = source.clone().into_bytes();
+        self.source = source.chars().collect();
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    // Scanning continues past errors, so all of them are reported along with the tokens found
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<ScanError>) {
         while !self.is_at_end() {
-            self.start = self.current;
+            self.mark_token_start();
             self.scan_token()
         }
+        self.mark_token_start();
         self.add_token_null(TokenType::EOF);
 
-        let tokens = self.tokens.clone();
-        tokens
+        (self.tokens.clone(), self.errors.clone())
+    }
+
+    fn mark_token_start(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_col = self.start - self.last_line_start + 1;
     }
 
     fn scan_token(&mut self) {
@@ -76,12 +113,35 @@ impl Scanner {
             ')' => self.add_token_null(TokenType::RightParen),
             '{' => self.add_token_null(TokenType::LeftBrace),
             '}' => self.add_token_null(TokenType::RightBrace),
+            '[' => self.add_token_null(TokenType::LeftBracket),
+            ']' => self.add_token_null(TokenType::RightBracket),
             ',' => self.add_token_null(TokenType::Comma),
             '.' => self.add_token_null(TokenType::Dot),
-            '-' => self.add_token_null(TokenType::Minus),
-            '+' => self.add_token_null(TokenType::Plus),
+            '-' => {
+                if self.match_next('-') {
+                    self.add_token_null(TokenType::MinusMinus);
+                } else {
+                    self.add_token_null(TokenType::Minus)
+                }}
+            '+' => {
+                if self.match_next('+') {
+                    self.add_token_null(TokenType::PlusPlus);
+                } else {
+                    self.add_token_null(TokenType::Plus)
+                }}
             ';' => self.add_token_null(TokenType::Semicolon),
-            '*' => self.add_token_null(TokenType::Star),
+            '*' => {
+                if self.match_next('*') {
+                    self.add_token_null(TokenType::StarStar);
+                } else {
+                    self.add_token_null(TokenType::Star)
+                }}
+            '%' => self.add_token_null(TokenType::Percent),
+            '?' => self.add_token_null(TokenType::Question),
+            ':' => self.add_token_null(TokenType::Colon),
+            '&' => self.add_token_null(TokenType::Ampersand),
+            '|' => self.add_token_null(TokenType::Pipe),
+            '^' => self.add_token_null(TokenType::Caret),
             '!' => {
                 if self.match_next('=') {
                     self.add_token_null(TokenType::BangEqual);
@@ -97,46 +157,40 @@ impl Scanner {
             '<' => {
                 if self.match_next('=') {
                     self.add_token_null(TokenType::LessEqual);
+                } else if self.match_next('<') {
+                    self.add_token_null(TokenType::LessLess);
                 } else {
                     self.add_token_null(TokenType::Less)
                 }}
             '>' => {
                 if self.match_next('=') {
                     self.add_token_null(TokenType::GreaterEqual);
+                } else if self.match_next('>') {
+                    self.add_token_null(TokenType::GreaterGreater);
                 } else {
                     self.add_token_null(TokenType::Greater)
                 }}
             '/' => {
                 if self.peek() == '/' {
                     self.current += 1;
-                    while self.peek() != '\n' {
+                    while !matches!(self.peek(), '\n' | '\r') && !self.is_at_end() {
                         self.current += 1;
                     }
                 } else if self.peek() == '*' {
                     self.current += 1;
-                    while self.peek() != '*' && self.peek_next() != '/' {
-                        if self.peek() == '\n' {
-                            self.line += 1;
-                            self.col = 0;
-                            self.last_line_start = self.current;
-                        }
-                        self.current += 1;
-                    }
-                    self.current += 2;
+                    self.block_comment();
                 } else {
                     self.add_token_null(TokenType::Slash);
                 }
             }
             ' ' => {},
-            '\r' => {},
             '\t' => {},
-            '\n' => {self.line += 1; self.col = 0; self.last_line_start = self.current;}
-            '"' => {self.string()}
-            'o' => {
-                if self.match_next('r') {
-                    self.add_token_null(TokenType::Or);
+            '\r' | '\n' => {
+                if self.ends_line(c) {
+                    self.new_line();
                 }
             }
+            '"' => {self.string()}
              _ => {
                  if self.match_digit(c) {
                      self.number();
@@ -149,12 +203,40 @@ impl Scanner {
         }
     }
 
+    fn block_comment(&mut self) {
+        loop {
+            if self.is_at_end() {
+                // Reported at the opening '/*', like an unterminated string
+                self.error(self.start_line, String::from("Unterminated block comment"));
+                return;
+            }
+            if self.peek() == '*' && self.peek_next() == '/' {
+                self.current += 2;
+                return;
+            }
+            let c = self.advance();
+            if self.ends_line(c) {
+                self.new_line();
+            }
+        }
+    }
+
+    // Called with c just consumed. "\r\n" is a single break counted at its '\n', a lone '\r' is a break of its own.
+    fn ends_line(&self, c: char) -> bool {
+        c == '\n' || (c == '\r' && self.peek() != '\n')
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.last_line_start = self.current;
+    }
+
     pub fn had_error(&self) -> bool {
-        self.had_error
+        !self.errors.is_empty()
     }
 
     fn match_alpha(&mut self, c: char) -> bool {
-        if (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_' {
+        if c.is_alphabetic() || c == '_' {
             true
         } else { false }
     }
@@ -167,7 +249,7 @@ impl Scanner {
         while self.match_alphanumeric(self.peek()) {
             self.advance();
         }
-        let text = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
+        let text: String = self.source[self.start..self.current].iter().collect();
         if self.keywords.contains_key(&text) {
             self.add_token_null(*self.keywords.get(&text).unwrap());
         } else {
@@ -176,20 +258,94 @@ impl Scanner {
     }
 
     fn number(&mut self) {
-        while self.match_digit(self.peek()) {
-            self.advance();
+        if self.source[self.start] == '0' {
+            match self.peek() {
+                'x' | 'X' => return self.radix_number(16, "hexadecimal"),
+                'b' | 'B' => return self.radix_number(2, "binary"),
+                _ => {}
+            }
+        }
+
+        if !self.digits() {
+            return;
         }
 
+        if self.peek() == '.' && self.peek_next() == '_' {
+            self.advance();
+            self.digits();
+            self.error(self.line, String::from("Underscore in number must be between digits"));
+            return;
+        }
         if self.peek() == '.' && self.match_digit(self.peek_next()) {
             self.advance();
-            while self.match_digit(self.peek()) {
+            if !self.digits() {
+                return;
+            }
+        }
+
+        if self.peek() == 'e' || self.peek() == 'E' {
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-' {
                 self.advance();
             }
+            if !self.match_digit(self.peek()) {
+                self.error(self.line, String::from("Expected digits in number exponent"));
+                return;
+            }
+            if !self.digits() {
+                return;
+            }
         }
 
-        self.add_token(TokenType::Number,
-                       Literal::Number(String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap().parse::<f64>().unwrap())
-        );
+        let text: String = self.source[self.start..self.current].iter().filter(|c| **c != '_').collect();
+        let n = text.parse::<f64>().unwrap();
+        if n.is_infinite() {
+            self.error(self.line, String::from("The number literal is too large"));
+            return;
+        }
+        self.add_token(TokenType::Number, Literal::Number(n));
+    }
+
+    // Integer literal with a 0x or 0b prefix, producing the same Number literal as decimals
+    fn radix_number(&mut self, radix: u32, name: &str) {
+        self.advance();
+        while self.match_alphanumeric(self.peek()) {
+            self.advance();
+        }
+
+        let text: String = self.source[self.start + 2..self.current].iter().collect();
+        if text.is_empty() {
+            self.error(self.line, format!("Expected digits in {} literal", name));
+            return;
+        }
+        if text.starts_with('_') || text.ends_with('_') || text.contains("__") {
+            self.error(self.line, String::from("Underscore in number must be between digits"));
+            return;
+        }
+        let text = text.replace('_', "");
+        if let Some(c) = text.chars().find(|c| !c.is_digit(radix)) {
+            self.error(self.line, format!("Invalid digit '{}' in {} literal", c, name));
+            return;
+        }
+        match u64::from_str_radix(&text, radix) {
+            Ok(n) => self.add_token(TokenType::Number, Literal::Number(n as f64)),
+            Err(_) => self.error(self.line, format!("The {} literal is too large", name)),
+        }
+    }
+
+    // Consumes a run of digits where single underscores may separate digits, as in 1_000_000.
+    // Reports a misplaced underscore after consuming the rest of the run.
+    fn digits(&mut self) -> bool {
+        let mut valid = true;
+        while self.match_digit(self.peek()) || self.peek() == '_' {
+            if self.advance() == '_' && !self.match_digit(self.peek()) {
+                valid = false;
+            }
+        }
+        if !valid {
+            self.error(self.line, String::from("Underscore in number must be between digits"));
+        }
+        valid
     }
 
     fn match_digit(&mut self, value: char) -> bool {
@@ -197,30 +353,93 @@ impl Scanner {
     }
 
     fn string(&mut self) {
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-                self.col = 0
+            let c = self.advance();
+            if self.ends_line(c) {
+                self.new_line();
+            }
+            if c == '\\' && !self.is_at_end() {
+                self.escape_sequence(&mut value);
+            } else {
+                value.push(c);
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            self.error(self.line, String::from("Unterminated string"));
+            // Reported at the opening quote, the end of the input says nothing about where the string began
+            self.error(self.start_line, String::from("Unterminated string"));
             return;
         }
 
         self.advance();
 
-        let value = String::from_utf8(self.source[self.start+1..self.current-1].to_vec()).unwrap();
+        let value = self.intern(value);
         self.add_token(TokenType::String, Literal::String(value));
     }
 
+    // Identical string literals share one allocation
+    fn intern(&mut self, value: String) -> Rc<str> {
+        if let Some(existing) = self.strings.get(value.as_str()) {
+            return existing.clone();
+        }
+        let interned: Rc<str> = Rc::from(value);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    // Errors point at the backslash rather than at the start of the string
+    fn escape_sequence(&mut self, value: &mut String) {
+        let (line, col) = (self.line, self.current - self.last_line_start);
+        let c = self.advance();
+        match c {
+            'n' => value.push('\n'),
+            't' => value.push('\t'),
+            'r' => value.push('\r'),
+            '\\' => value.push('\\'),
+            '"' => value.push('"'),
+            '0' => value.push('\0'),
+            'u' => self.unicode_escape(value, line, col),
+            '\n' | '\r' => {
+                if self.ends_line(c) {
+                    self.new_line();
+                }
+                self.error_at(line, col, String::from("Invalid escape sequence '\\' at end of line in string"))
+            }
+            _ => self.error_at(line, col, format!("Invalid escape sequence '\\{}' in string", c)),
+        }
+    }
+
+    // \u{1F600}: up to six hex digits naming a Unicode scalar value
+    fn unicode_escape(&mut self, value: &mut String, line: usize, col: usize) {
+        if !self.match_next('{') {
+            self.error_at(line, col, String::from("Expected '{' after '\\u' in string"));
+            return;
+        }
+        let mut digits = String::new();
+        while self.peek() != '}' && self.peek() != '"' && !self.is_at_end() {
+            digits.push(self.advance());
+        }
+        if !self.match_next('}') {
+            self.error_at(line, col, String::from("Expected '}' after unicode escape in string"));
+            return;
+        }
+        if digits.is_empty() {
+            self.error_at(line, col, String::from("Empty unicode escape in string"));
+            return;
+        }
+        let code = if digits.len() <= 6 { u32::from_str_radix(&digits, 16).ok() } else { None };
+        match code.and_then(char::from_u32) {
+            Some(c) => value.push(c),
+            None => self.error_at(line, col, format!("Invalid unicode escape '\\u{{{}}}' in string", digits)),
+        }
+    }
+
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             '\0'
         } else {
-            self.source[self.current + 1] as char
+            self.source[self.current + 1]
         }
     }
 
@@ -228,7 +447,7 @@ impl Scanner {
         if self.is_at_end() {
             '\0'
         } else {
-            self.source[self.current] as char
+            self.source[self.current]
         }
     }
 
@@ -236,24 +455,23 @@ impl Scanner {
         if self.is_at_end() {
             return false;
         }
-        if self.source[self.current] as char != expected {
+        if self.source[self.current] != expected {
             return false;
         }
         self.current += 1;
         return true;
     }
 
-    fn report(&mut self, line: usize, where_: String, message: String) {
-        eprintln!("line {}: Error {}: {}", line, where_, message);
-        self.had_error = true;
+    fn error(&mut self, line: usize, message: String) {
+        self.errors.push(ScanError{message, line, col: self.start_col});
     }
 
-    fn error(&mut self, line: usize, message: String) {
-        self.report(line, String::from(""), message);
+    fn error_at(&mut self, line: usize, col: usize, message: String) {
+        self.errors.push(ScanError{message, line, col});
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source[self.current] as char;
+        let c = self.source[self.current];
         self.current += 1;
         c
     }
@@ -263,7 +481,7 @@ impl Scanner {
     }
 
     fn add_token(&mut self, token: TokenType, literal: Literal) {
-        let text = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
-        self.tokens.push(Token{token_type: token, lexeme: text, literal, line: self.line, col: self.current - self.last_line_start})
+        let text: String = self.source[self.start..self.current].iter().collect();
+        self.tokens.push(Token::new(token, text, literal, self.start_line, self.start_col))
     }
 }
\ No newline at end of file
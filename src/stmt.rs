@@ -2,22 +2,66 @@ use std::fmt::{Display, Write};
 use crate::expr::Expr;
 use crate::token::Token;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Stmt {
-    VarDeclaration(Token, Option<Expr>),
+    VarDeclaration(Token, Option<Expr>, bool),  // Name, initializer, declared with `const`
     Print(Expr),
     Expression(Expr),
     Block(Vec<Stmt>),
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
+    While(Token, Expr, Box<Stmt>, Option<Expr>),  // `while` or `for` keyword, condition, body, increment of a for loop
+    ForEach(Token, Expr, Box<Stmt>),  // Loop variable, array, body
+    Break,
+    Continue,
     Function(Token, Vec<Token>, Vec<Stmt>),
+    Return(Token, Option<Expr>),
+    Class(Token, Option<Expr>, Vec<Stmt>),  // Name, superclass variable, methods
+}
+
+// One method per Stmt variant, see ExprVisitor
+pub trait StmtVisitor<R> {
+    fn visit_var_declaration(&mut self, name: &Token, initializer: Option<&Expr>, constant: bool) -> R;
+    fn visit_print(&mut self, expr: &Expr) -> R;
+    fn visit_expression(&mut self, expr: &Expr) -> R;
+    fn visit_block(&mut self, statements: &[Stmt]) -> R;
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> R;
+    fn visit_while(&mut self, keyword: &Token, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> R;
+    fn visit_for_each(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> R;
+    fn visit_break(&mut self) -> R;
+    fn visit_continue(&mut self) -> R;
+    fn visit_function(&mut self, name: &Token, params: &[Token], body: &[Stmt]) -> R;
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expr>) -> R;
+    fn visit_class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Stmt]) -> R;
+}
+
+impl Stmt {
+    pub fn accept<R>(&self, visitor: &mut impl StmtVisitor<R>) -> R {
+        match self {
+            Stmt::VarDeclaration(name, initializer, constant) =>
+                visitor.visit_var_declaration(name, initializer.as_ref(), *constant),
+            Stmt::Print(expr) => visitor.visit_print(expr),
+            Stmt::Expression(expr) => visitor.visit_expression(expr),
+            Stmt::Block(statements) => visitor.visit_block(statements),
+            Stmt::If(condition, then_branch, else_branch) =>
+                visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            Stmt::While(keyword, condition, body, increment) =>
+                visitor.visit_while(keyword, condition, body, increment.as_ref()),
+            Stmt::ForEach(name, iterable, body) => visitor.visit_for_each(name, iterable, body),
+            Stmt::Break => visitor.visit_break(),
+            Stmt::Continue => visitor.visit_continue(),
+            Stmt::Function(name, params, body) => visitor.visit_function(name, params, body),
+            Stmt::Return(keyword, value) => visitor.visit_return(keyword, value.as_ref()),
+            Stmt::Class(name, superclass, methods) => visitor.visit_class(name, superclass.as_ref(), methods),
+        }
+    }
 }
 
 impl Display for Stmt {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Stmt::VarDeclaration(t, e) => {
-                let mut ret = String::from(format!("VarDeclaration {}", *t).as_str());
+            Stmt::VarDeclaration(t, e, constant) => {
+                let kind = if *constant { "ConstDeclaration" } else { "VarDeclaration" };
+                let mut ret = String::from(format!("{} {}", kind, *t).as_str());
                 if e.is_some() {
                     ret.write_str(format!("= {}", e.clone().unwrap()).as_str()).expect("");
                 }
@@ -38,20 +82,37 @@ impl Display for Stmt {
                 }
                 fmt.write_str(&*ret)
             }
-            Stmt::While(e, s) => {
-                fmt.write_str(format!("While [{}] [{}]", e, *s).as_str())
+            Stmt::While(_keyword, e, s, increment) => {
+                match increment {
+                    Some(i) => fmt.write_str(format!("While [{}] [{}] [{}]", e, *s, i).as_str()),
+                    None => fmt.write_str(format!("While [{}] [{}]", e, *s).as_str()),
+                }
             }
+            Stmt::ForEach(name, iterable, body) => {
+                fmt.write_str(format!("ForEach {} in [{}] [{}]", name.lexeme, iterable, body).as_str())
+            }
+            Stmt::Break => fmt.write_str("Break"),
+            Stmt::Continue => fmt.write_str("Continue"),
             Stmt::Print(e) => fmt.write_str(format!("Print {}", e).as_str()),
             Stmt::Function(name, _params, _body) => {
                 fmt.write_str(format!("fun {}", &name.lexeme).as_str())
             }
+            Stmt::Class(name, superclass, _methods) => {
+                match superclass {
+                    Some(Expr::Variable(s, _)) =>
+                        fmt.write_str(format!("class {} < {}", &name.lexeme, &s.lexeme).as_str()),
+                    _ => fmt.write_str(format!("class {}", &name.lexeme).as_str()),
+                }
+            }
+            Stmt::Return(_keyword, e) => {
+                match e {
+                    Some(e) => fmt.write_str(format!("Return {}", e).as_str()),
+                    None => fmt.write_str("Return"),
+                }
+            }
             // Stmt::NativeFunction(name, params, body) => {
             //     fmt.write_str(format!("fun {}({})", &name.lexeme, *params.iter().map(|x| *x.lexeme).collect().join(", ")).as_str())
             // }
         }
     }
 }
-
-impl Stmt {
-
-}
\ No newline at end of file
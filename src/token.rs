@@ -1,3 +1,8 @@
+use std::rc::Rc;
+use crate::diagnostic::json_escape;
+#[cfg(feature = "clone-stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
@@ -5,6 +10,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -12,6 +19,12 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Question,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
 
     // One or two character tokens.
     Bang,
@@ -22,6 +35,11 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    StarStar,
+    PlusPlus,
+    MinusMinus,
+    LessLess,
+    GreaterGreater,
 
     // Literals.
     Identifier,
@@ -30,12 +48,16 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
     Class,
+    Const,
+    Continue,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -56,6 +78,8 @@ impl std::fmt::Display for TokenType {
             TokenType::RightParen => write!(f, "RightParen"),
             TokenType::LeftBrace => write!(f, "LeftBrace"),
             TokenType::RightBrace => write!(f, "RightBrace"),
+            TokenType::LeftBracket => write!(f, "LeftBracket"),
+            TokenType::RightBracket => write!(f, "RightBracket"),
             TokenType::Comma => write!(f, "COMMA"),
             TokenType::Dot => write!(f, "DOT"),
             TokenType::Minus => write!(f, "MINUS"),
@@ -63,6 +87,12 @@ impl std::fmt::Display for TokenType {
             TokenType::Semicolon => write!(f, "SEMICOLON"),
             TokenType::Slash => write!(f, "SLASH"),
             TokenType::Star => write!(f, "STAR"),
+            TokenType::Percent => write!(f, "PERCENT"),
+            TokenType::Question => write!(f, "QUESTION"),
+            TokenType::Colon => write!(f, "COLON"),
+            TokenType::Ampersand => write!(f, "AMPERSAND"),
+            TokenType::Pipe => write!(f, "PIPE"),
+            TokenType::Caret => write!(f, "CARET"),
             TokenType::Bang => write!(f, "BANG"),
             TokenType::BangEqual => write!(f, "BangEqual"),
             TokenType::Equal => write!(f, "EQUAL"),
@@ -71,16 +101,25 @@ impl std::fmt::Display for TokenType {
             TokenType::GreaterEqual => write!(f, "GreaterEqual"),
             TokenType::Less => write!(f, "LESS"),
             TokenType::LessEqual => write!(f, "LessEqual"),
+            TokenType::StarStar => write!(f, "StarStar"),
+            TokenType::PlusPlus => write!(f, "PlusPlus"),
+            TokenType::MinusMinus => write!(f, "MinusMinus"),
+            TokenType::LessLess => write!(f, "LessLess"),
+            TokenType::GreaterGreater => write!(f, "GreaterGreater"),
             TokenType::Identifier => write!(f, "IDENTIFIER"),
             TokenType::String => write!(f, "STRING"),
             TokenType::Number => write!(f, "NUMBER"),
             TokenType::And => write!(f, "AND"),
+            TokenType::Break => write!(f, "BREAK"),
             TokenType::Class => write!(f, "CLASS"),
+            TokenType::Const => write!(f, "CONST"),
+            TokenType::Continue => write!(f, "CONTINUE"),
             TokenType::Else => write!(f, "ELSE"),
             TokenType::False => write!(f, "FALSE"),
             TokenType::Fun => write!(f, "FUN"),
             TokenType::For => write!(f, "FOR"),
             TokenType::If => write!(f, "IF"),
+            TokenType::In => write!(f, "IN"),
             TokenType::Nil => write!(f, "NIL"),
             TokenType::Or => write!(f, "OR"),
             TokenType::Print => write!(f, "PRINT"),
@@ -95,7 +134,8 @@ impl std::fmt::Display for TokenType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
+#[cfg_attr(not(feature = "clone-stats"), derive(Clone))]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
@@ -104,8 +144,40 @@ pub struct Token {
     pub col: usize,
 }
 
+// Every clone copies the lexeme, so tests count them to keep the parser's hot paths honest.
+// Only built with the clone-stats feature, which the parser_clones test needs.
+#[cfg(feature = "clone-stats")]
+static TOKEN_CLONES: AtomicUsize = AtomicUsize::new(0);
+
 impl Token {
+    pub fn new(token_type: TokenType, lexeme: String, literal: Literal, line: usize, col: usize) -> Token {
+        Token{token_type, lexeme, literal, line, col}
+    }
+
+    // Clones made so far by the whole process
+    #[cfg(feature = "clone-stats")]
+    pub fn clone_count() -> usize {
+        TOKEN_CLONES.load(Ordering::Relaxed)
+    }
+
+    pub fn to_json(&self) -> String {
+        format!("{{\"type\":\"{}\",\"lexeme\":\"{}\",\"literal\":{},\"line\":{},\"col\":{}}}",
+                self.token_type, json_escape(&self.lexeme), self.literal.to_json(), self.line, self.col)
+    }
+}
 
+// Machine-readable counterpart of the --tokens dump, for editors and other tools
+pub fn to_json_array(tokens: &[Token]) -> String {
+    let items: Vec<String> = tokens.iter().map(|t| t.to_json()).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(feature = "clone-stats")]
+impl Clone for Token {
+    fn clone(&self) -> Token {
+        TOKEN_CLONES.fetch_add(1, Ordering::Relaxed);
+        Token::new(self.token_type, self.lexeme.clone(), self.literal.clone(), self.line, self.col)
+    }
 }
 
 impl std::fmt::Display for Token {
@@ -129,12 +201,29 @@ impl std::fmt::Display for Token {
 pub enum Literal {
     Null,
     Identifier(String),
-    String(String),
+    // Shared between identical literals, see Scanner::intern
+    String(Rc<str>),
     Number(f64),
     True,
     False,
 }
 
+impl Literal {
+    // Numbers and booleans keep their JSON types, keywords without a value become null, and so
+    // do infinite numbers, which JSON can't express
+    pub fn to_json(&self) -> String {
+        match self {
+            Literal::Null => String::from("null"),
+            Literal::Number(n) if !n.is_finite() => String::from("null"),
+            Literal::Identifier(s) => format!("\"{}\"", json_escape(s)),
+            Literal::String(s) => format!("\"{}\"", json_escape(s)),
+            Literal::Number(n) => n.to_string(),
+            Literal::True => String::from("true"),
+            Literal::False => String::from("false"),
+        }
+    }
+}
+
 impl std::fmt::Display for Literal {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
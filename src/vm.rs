@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
 pub enum OpCode {
@@ -8,6 +9,38 @@ pub enum OpCode {
     OpSubtract,
     OpMultiply,
     OpDivide,
+    OpModulo,
+    OpNil,
+    OpTrue,
+    OpFalse,
+    OpConstantLong,
+    OpJump,
+    OpJumpIfFalse,
+    OpLoop,
+    OpPrint,
+    OpDefineGlobal,
+    OpGetGlobal,
+    OpSetGlobal,
+    OpPower,
+    OpPop,
+}
+
+// How the operand bytes following an opcode are interpreted
+#[derive(Clone, Copy, PartialEq)]
+enum OperandKind {
+    None,
+    Constant,
+    // Forward distance from the end of the instruction
+    Jump,
+    // Backward distance from the end of the instruction
+    Loop,
+}
+
+// Operand layout of an opcode. The disassembler walks the code with it, so every opcode's
+// width is stated in one place.
+struct OpLayout {
+    kind: OperandKind,
+    width: usize,
 }
 
 impl From<u8> for OpCode {
@@ -20,18 +53,44 @@ impl From<u8> for OpCode {
             4 => OpCode::OpSubtract,
             5 => OpCode::OpMultiply,
             6 => OpCode::OpDivide,
+            7 => OpCode::OpModulo,
+            8 => OpCode::OpNil,
+            9 => OpCode::OpTrue,
+            10 => OpCode::OpFalse,
+            11 => OpCode::OpConstantLong,
+            12 => OpCode::OpJump,
+            13 => OpCode::OpJumpIfFalse,
+            14 => OpCode::OpLoop,
+            15 => OpCode::OpPrint,
+            16 => OpCode::OpDefineGlobal,
+            17 => OpCode::OpGetGlobal,
+            18 => OpCode::OpSetGlobal,
+            19 => OpCode::OpPower,
+            20 => OpCode::OpPop,
             _ => {eprintln!("Unknown opcode conversion attempt: {}", value); std::process::exit(1)}
         }
     }
 }
 
+const STACK_MAX: usize = 256;
+
+// Unwraps a stack operation, turning its error into a runtime error that ends Vm::run
+macro_rules! try_stack {
+    ($self:ident, $e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(message) => return $self.runtime_error(message),
+        }
+    };
+}
+
 macro_rules! binary_op {
     ($self:ident, $op:tt) => {{
-        let b = $self.pop();
-        let a = $self.pop();
+        let b = try_stack!($self, $self.pop());
+        let a = try_stack!($self, $self.pop());
         match (a, b) {
-            (VmValue::Double(a_), VmValue::Double(b_)) => $self.push(VmValue::Double(a_ $op b_)),
-            // _ => panic!()
+            (VmValue::Double(a_), VmValue::Double(b_)) => try_stack!($self, $self.push(VmValue::Double(a_ $op b_))),
+            _ => return $self.runtime_error("Operands must be numbers"),
         }
     }};
 }
@@ -46,16 +105,47 @@ impl Display for OpCode {
             OpCode::OpSubtract => f.write_str("OpSubtract"),
             OpCode::OpMultiply => f.write_str("OpMultiply"),
             OpCode::OpDivide => f.write_str("OpDivide"),
+            OpCode::OpModulo => f.write_str("OpModulo"),
+            OpCode::OpNil => f.write_str("OpNil"),
+            OpCode::OpTrue => f.write_str("OpTrue"),
+            OpCode::OpFalse => f.write_str("OpFalse"),
+            OpCode::OpConstantLong => f.write_str("OpConstantLong"),
+            OpCode::OpJump => f.write_str("OpJump"),
+            OpCode::OpJumpIfFalse => f.write_str("OpJumpIfFalse"),
+            OpCode::OpLoop => f.write_str("OpLoop"),
+            OpCode::OpPrint => f.write_str("OpPrint"),
+            OpCode::OpDefineGlobal => f.write_str("OpDefineGlobal"),
+            OpCode::OpGetGlobal => f.write_str("OpGetGlobal"),
+            OpCode::OpSetGlobal => f.write_str("OpSetGlobal"),
+            OpCode::OpPower => f.write_str("OpPower"),
+            OpCode::OpPop => f.write_str("OpPop"),
         }
     }
 }
 
+impl OpCode {
+    fn layout(&self) -> OpLayout {
+        match self {
+            // The global opcodes name their variable with a string constant
+            OpCode::OpConstant | OpCode::OpDefineGlobal | OpCode::OpGetGlobal | OpCode::OpSetGlobal =>
+                OpLayout{kind: OperandKind::Constant, width: 1},
+            OpCode::OpConstantLong => OpLayout{kind: OperandKind::Constant, width: 3},
+            OpCode::OpJump | OpCode::OpJumpIfFalse => OpLayout{kind: OperandKind::Jump, width: 2},
+            OpCode::OpLoop => OpLayout{kind: OperandKind::Loop, width: 2},
+            OpCode::OpReturn | OpCode::OpNegate | OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply |
+            OpCode::OpDivide | OpCode::OpModulo | OpCode::OpNil | OpCode::OpTrue | OpCode::OpFalse |
+            OpCode::OpPrint | OpCode::OpPower | OpCode::OpPop =>
+                OpLayout{kind: OperandKind::None, width: 0},
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Chunk {
-    count: usize,
-    capacity: usize,
-    code: Box<[u8]>,
+    code: Vec<u8>,
     value_array: ValueArray,
-    src_location: Box<[SrcLocation]>
+    // Run-length encoded: each location with the number of consecutive bytes it covers
+    src_location: Vec<(SrcLocation, usize)>,
 }
 
 pub struct Vm {
@@ -63,7 +153,9 @@ pub struct Vm {
     ip: usize,
     debug_disassemble_instructions: bool,
     stack: Box<[VmValue]>,
-    stack_top: usize
+    stack_top: usize,
+    // Kept across chunks, so later statements see earlier definitions
+    globals: HashMap<String, VmValue>,
 }
 
 
@@ -72,8 +164,9 @@ impl Default for Vm {
         Vm{chunk: Chunk::default(),
             ip: 0,
             debug_disassemble_instructions: false,
-            stack: vec![VmValue::Double(0.0); 256].into_boxed_slice(),
+            stack: vec![VmValue::Nil; STACK_MAX].into_boxed_slice(),
             stack_top: 0,
+            globals: HashMap::new(),
         }
     }
 }
@@ -84,14 +177,38 @@ impl Vm {
     //     self.stack_top = 0;
     // }
 
-    fn push(&mut self, vm_value: VmValue) {
+    fn push(&mut self, vm_value: VmValue) -> Result<(), &'static str> {
+        if self.stack_top >= STACK_MAX {
+            return Err("Stack overflow");
+        }
         self.stack[self.stack_top] = vm_value;
         self.stack_top += 1;
+        Ok(())
     }
 
-    fn pop(&mut self) -> VmValue {
+    fn pop(&mut self) -> Result<VmValue, &'static str> {
+        if self.stack_top == 0 {
+            return Err("Stack underflow");
+        }
         self.stack_top -= 1;
-        return self.stack[self.stack_top].clone();
+        Ok(self.stack[self.stack_top].clone())
+    }
+
+    // Reports the error at the location of the current instruction and leaves the VM ready for the next chunk
+    fn runtime_error(&mut self, message: &str) -> InterpretResult {
+        eprintln!("{} at {}", message, self.chunk.line_at(self.ip - 1));
+        self.stack_top = 0;
+        InterpretResult::InterpretRuntimeError
+    }
+
+    // Values left on the stack, zero between statements
+    pub fn stack_len(&self) -> usize {
+        self.stack_top
+    }
+
+    // Current value of a global variable, None when it was never defined
+    pub fn global(&self, name: &str) -> Option<&VmValue> {
+        self.globals.get(name)
     }
 
     pub fn enable_debug(&mut self) {
@@ -112,36 +229,127 @@ impl Vm {
                     print!("[{}] ", self.stack[i])
                 }
                 println!();
-                self.chunk.disassemble_instruction(self.ip);
+                let mut instruction = String::new();
+                self.chunk.disassemble_instruction(self.ip, &mut instruction);
+                print!("{}", instruction);
             }
             let instruction = OpCode::from(self.read_byte());
             match instruction {
-                OpCode::OpReturn => {
-                    let val = &self.pop();
+                OpCode::OpReturn => return InterpretResult::InterpretOk,
+                OpCode::OpPrint => {
+                    let val = &try_stack!(self, self.pop());
                     self.chunk.print_value(val);
-                    return InterpretResult::InterpretOk;
-                },
+                    println!();
+                }
+                // The result of an expression statement is not needed
+                OpCode::OpPop => {
+                    try_stack!(self, self.pop());
+                }
                 OpCode::OpConstant => {
-                    let value = self.read_constant();
-                    self.push(value.clone());
-                    println!("{}", value);
+                    let index = self.read_byte() as usize;
+                    let value = self.read_constant(index);
+                    try_stack!(self, self.push(value));
+                }
+                OpCode::OpConstantLong => {
+                    let index = self.read_long_operand();
+                    let value = self.read_constant(index);
+                    try_stack!(self, self.push(value));
                 }
                 OpCode::OpNegate => {
-                    let tmp = self.pop();
+                    let tmp = try_stack!(self, self.pop());
                     match tmp {
-                        VmValue::Double(f) => self.push(VmValue::Double(-f)),
+                        VmValue::Double(f) => try_stack!(self, self.push(VmValue::Double(-f))),
+                        _ => return self.runtime_error("Operand must be a number"),
                     }
                 }
                 OpCode::OpAdd => binary_op!(self, +),
                 OpCode::OpSubtract => binary_op!(self, -),
                 OpCode::OpMultiply => binary_op!(self, *),
+                // Like the tree-walker, division and modulo by zero follow IEEE 754 (inf/NaN)
                 OpCode::OpDivide => binary_op!(self, /),
+                OpCode::OpModulo => binary_op!(self, %),
+                OpCode::OpPower => {
+                    let b = try_stack!(self, self.pop());
+                    let a = try_stack!(self, self.pop());
+                    match (a, b) {
+                        (VmValue::Double(a), VmValue::Double(b)) => try_stack!(self, self.push(VmValue::Double(a.powf(b)))),
+                        _ => return self.runtime_error("Operands must be numbers"),
+                    }
+                }
+                OpCode::OpNil => try_stack!(self, self.push(VmValue::Nil)),
+                OpCode::OpTrue => try_stack!(self, self.push(VmValue::Bool(true))),
+                OpCode::OpFalse => try_stack!(self, self.push(VmValue::Bool(false))),
+                OpCode::OpDefineGlobal => {
+                    let name = self.read_name();
+                    let value = try_stack!(self, self.pop());
+                    self.globals.insert(name, value);
+                }
+                OpCode::OpGetGlobal => {
+                    let name = self.read_name();
+                    let value = match self.globals.get(&name) {
+                        Some(value) => value.clone(),
+                        None => return self.runtime_error(&format!("Undefined variable '{}'", name)),
+                    };
+                    try_stack!(self, self.push(value));
+                }
+                // Assignment is an expression, the value stays on the stack
+                OpCode::OpSetGlobal => {
+                    let name = self.read_name();
+                    if !self.globals.contains_key(&name) {
+                        return self.runtime_error(&format!("Undefined variable '{}'", name));
+                    }
+                    let value = try_stack!(self, self.peek());
+                    self.globals.insert(name, value);
+                }
+                OpCode::OpJump => {
+                    let offset = self.read_short_operand();
+                    self.ip += offset;
+                }
+                // Pops the condition, only nil and false jump
+                OpCode::OpJumpIfFalse => {
+                    let offset = self.read_short_operand();
+                    match try_stack!(self, self.pop()) {
+                        VmValue::Nil | VmValue::Bool(false) => self.ip += offset,
+                        _ => {}
+                    }
+                }
+                OpCode::OpLoop => {
+                    let offset = self.read_short_operand();
+                    self.ip -= offset;
+                }
             }
         }
     }
 
-    fn read_constant(&mut self) -> VmValue {
-        self.chunk.value_array.values[self.read_byte() as usize].clone()
+    fn peek(&self) -> Result<VmValue, &'static str> {
+        if self.stack_top == 0 {
+            return Err("Stack underflow");
+        }
+        Ok(self.stack[self.stack_top - 1].clone())
+    }
+
+    fn read_name(&mut self) -> String {
+        let index = self.read_byte() as usize;
+        match self.read_constant(index) {
+            VmValue::Obj(name) => name,
+            value => value.to_string(),
+        }
+    }
+
+    fn read_constant(&self, index: usize) -> VmValue {
+        self.chunk.value_array.values[index].clone()
+    }
+
+    fn read_short_operand(&mut self) -> usize {
+        let operand = self.chunk.read_operand(self.ip, 2);
+        self.ip += 2;
+        operand
+    }
+
+    fn read_long_operand(&mut self) -> usize {
+        let operand = self.chunk.read_operand(self.ip, 3);
+        self.ip += 3;
+        operand
     }
 
     fn read_byte(&mut self) -> u8 {
@@ -157,132 +365,159 @@ pub enum InterpretResult {
     InterpretRuntimeError
 }
 
-impl Default for Chunk {
-    fn default() -> Self {
-        Chunk{count: 0, capacity: 0,
-            code: vec![].into_boxed_slice(),
-            value_array: ValueArray::default(),
-            src_location: vec![].into_boxed_slice(),
+impl Chunk {
+    pub fn write_chunk(&mut self, byte: u8, src_location: SrcLocation) {
+        self.code.push(byte);
+        match self.src_location.last_mut() {
+            Some((last, count)) if *last == src_location => *count += 1,
+            _ => self.src_location.push((src_location, 1)),
         }
     }
-}
 
-impl Chunk {
-    pub fn write_chunk(&mut self, byte: u8, src_location: SrcLocation) {
-        if self.capacity < self.count + 1 {
-            self.capacity = if self.capacity < 8 { 8 } else { self.capacity * 2 };
-            let mut code = vec![0u8; self.capacity].into_boxed_slice();
-            self.code.iter().enumerate().for_each(|(n, e)| code[n] = *e);
-            self.code = code;
-            let mut src_location = vec![SrcLocation{line: 0, col: 0}; self.capacity].into_boxed_slice();
-            self.src_location.iter().enumerate().for_each(|(n, e)| src_location[n] = (*e).clone());
-            self.src_location = src_location;
+    pub fn count(&self) -> usize {
+        self.code.len()
+    }
 
-        }
-        self.code[self.count] = byte;
-        self.src_location[self.count] = src_location;
-        self.count += 1;
+    pub fn code(&self) -> &[u8] {
+        &self.code
     }
 
-    pub fn add_constant(&mut self, value: f64) -> u8 {
-        let vm_value = VmValue::Double(value);
-        self.value_array.write_value(vm_value);
-        (self.value_array.count - 1) as u8
+    // Overwrites an already written byte, used to fill in jump distances
+    pub fn patch(&mut self, offset: usize, byte: u8) {
+        self.code[offset] = byte;
     }
 
-    pub fn disassemble(&self, name: &str) {
-        println!("Chunk {}: ", name);
-        let mut offset = 0usize;
-        loop {
-            offset = self.disassemble_instruction(offset);
-            if offset >= self.count {
-                break;
+    // Entries in the run-length encoded location table
+    pub fn location_runs(&self) -> usize {
+        self.src_location.len()
+    }
+
+    pub fn line_at(&self, offset: usize) -> SrcLocation {
+        let mut end = 0usize;
+        for (location, count) in &self.src_location {
+            end += count;
+            if offset < end {
+                return location.clone();
             }
         }
+        SrcLocation{line: 0, col: 0}
     }
 
-    fn disassemble_instruction(&self, offset: usize) -> usize {
-        print!("\t{:04} ", offset);
+    pub fn add_constant(&mut self, value: VmValue) -> usize {
+        self.value_array.values.push(value);
+        self.value_array.values.len() - 1
+    }
 
-        if offset > 0 && self.src_location[offset] == self.src_location[offset - 1] {
-            print!("   | ");
+    // Adds the constant and the instruction loading it. Indexes above 255 need OpConstantLong
+    // with a 3 byte big-endian operand.
+    pub fn write_constant(&mut self, value: VmValue, src_location: SrcLocation) {
+        let constant = self.add_constant(value);
+        if constant <= u8::MAX as usize {
+            self.write_chunk(OpCode::OpConstant as u8, src_location.clone());
+            self.write_chunk(constant as u8, src_location);
         } else {
-            print!("{} ", self.src_location[offset]);
+            self.write_chunk(OpCode::OpConstantLong as u8, src_location.clone());
+            self.write_chunk((constant >> 16) as u8, src_location.clone());
+            self.write_chunk((constant >> 8) as u8, src_location.clone());
+            self.write_chunk(constant as u8, src_location);
         }
+    }
 
-        let op = OpCode::from(self.code[offset]);
-        return match op {
-            OpCode::OpReturn => self.simple_instruction(op, offset),
-            OpCode::OpConstant => self.constant_instruction(op, offset),
-            OpCode::OpNegate => self.simple_instruction(op, offset),
-            OpCode::OpAdd => self.simple_instruction(op, offset),
-            OpCode::OpSubtract => self.simple_instruction(op, offset),
-            OpCode::OpMultiply => self.simple_instruction(op, offset),
-            OpCode::OpDivide => self.simple_instruction(op, offset),
-            // _ => { println!("Unknown opcode: {}", op); offset + 1 }
-        }
+    // Multi-byte operands are big-endian
+    fn read_operand(&self, offset: usize, width: usize) -> usize {
+        self.code[offset..offset + width].iter().fold(0, |operand, byte| operand << 8 | *byte as usize)
     }
 
-    fn constant_instruction(&self, op: OpCode, offset: usize) -> usize {
-        let constant = self.code[offset + 1] as usize;
-        print!("{:-16} {:04} '", op, constant);
-        self.print_value(&self.value_array.values[constant]);
-        offset + 2
+    pub fn disassemble(&self, name: &str) {
+        print!("{}", self.disassembly(name));
     }
 
-    fn print_value(&self, vm_value: &VmValue) {
-        println!("{}'", vm_value);
+    // The listing disassemble prints, one instruction per line
+    pub fn disassembly(&self, name: &str) -> String {
+        let mut out = format!("Chunk {}: \n", name);
+        let mut offset = 0usize;
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(offset, &mut out);
+        }
+        out
+    }
+
+    fn disassemble_instruction(&self, offset: usize, out: &mut String) -> usize {
+        out.push_str(format!("\t{:04} ", offset).as_str());
+
+        // Only the line matters here, instructions from one line share a `|` whatever their columns
+        let location = self.line_at(offset);
+        if offset > 0 && location.on_same_line(&self.line_at(offset - 1)) {
+            out.push_str("   | ");
+        } else {
+            out.push_str(format!("{} ", location).as_str());
+        }
+
+        let op = OpCode::from(self.code[offset]);
+        let layout = op.layout();
+        let next = offset + 1 + layout.width;
+        let operand = self.read_operand(offset + 1, layout.width);
+        let text = match layout.kind {
+            OperandKind::None => format!("{}", op),
+            OperandKind::Constant => format!("{:-16} {:04} '{}'", op, operand, self.value_array.values[operand]),
+            OperandKind::Jump => format!("{:-16} {:04} -> {:04}", op, offset, next + operand),
+            OperandKind::Loop => format!("{:-16} {:04} -> {:04}", op, offset, next - operand),
+        };
+        out.push_str(format!("{}\n", text).as_str());
+        next
     }
 
-    fn simple_instruction(&self, op: OpCode, offset: usize) -> usize {
-        println!("{}", op);
-        offset + 1
+    fn print_value(&self, vm_value: &VmValue) {
+        print!("{}", vm_value);
     }
 }
 
 #[derive(Clone)]
 pub enum VmValue {
-    Double(f64)
+    Double(f64),
+    Bool(bool),
+    Nil,
+    Obj(String),
 }
 
 
 impl Display for VmValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            VmValue::Double(d) => f.write_str(d.to_string().as_str()),
+            VmValue::Double(d) => f.write_str(format_number(*d).as_str()),
+            VmValue::Bool(b) => f.write_str(b.to_string().as_str()),
+            VmValue::Nil => f.write_str("nil"),
+            VmValue::Obj(s) => f.write_str(s.as_str()),
         }
     }
 }
 
-struct ValueArray {
-    count: usize,
-    capacity: usize,
-    values: Box<[VmValue]>,
-}
-
-impl Default for ValueArray {
-    fn default() -> Self {
-        ValueArray{count: 0, capacity: 0, values: vec![].into_boxed_slice()}
+// Shared by the VM and the tree-walker: whole numbers print without a fraction, others use the
+// shortest representation that reads back to the same value. Integers too large to be exact switch
+// to exponent notation.
+pub fn format_number(n: f64) -> String {
+    if n.is_finite() && n.abs() >= 1e16 {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
     }
 }
 
-impl ValueArray {
-    fn write_value(&mut self, value: VmValue) {
-        if self.capacity < self.count + 1 {
-            self.capacity = if self.capacity < 8 { 8 } else { self.capacity * 2 };
-            let mut values = vec![VmValue::Double(0f64); self.capacity].into_boxed_slice();
-            self.values.iter().enumerate().for_each(|(n, e)| values[n] = (*e).clone());
-            self.values = values;
-        }
-        self.values[self.count] = value;
-        self.count += 1;
-    }
+#[derive(Default)]
+struct ValueArray {
+    values: Vec<VmValue>,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SrcLocation {
-    pub(crate) line: usize,
-    pub(crate) col: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl SrcLocation {
+    pub fn on_same_line(&self, other: &SrcLocation) -> bool {
+        self.line == other.line
+    }
 }
 
 impl Display for SrcLocation {
@@ -0,0 +1,27 @@
+use crafting_rust::ast_dot::to_dot;
+use crafting_rust::{Parser, Scanner};
+
+fn dot(source: &str) -> String {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    to_dot(&Parser::default().parse(tokens).unwrap())
+}
+
+#[test]
+fn precedence_shows_in_the_edges() {
+    let dot = dot("1 + 2 * 3;");
+    assert!(dot.starts_with("digraph ast {\n"));
+    assert!(dot.ends_with("}\n"));
+    // The expression statement, both operators and the three numbers
+    assert_eq!(dot.matches("[label=").count() - dot.matches(" -> ").count(), 6);
+    assert_eq!(dot.matches(" -> ").count(), 5);
+    assert!(dot.contains("n1 [label=\"+\"];"));
+    assert!(dot.contains("n3 [label=\"*\"];"));
+    assert!(dot.contains("n1 -> n3 [label=\"right\"];"));
+}
+
+#[test]
+fn labels_are_escaped() {
+    assert!(dot(r#"print "say \"hi\"";"#).contains(r#"n1 [label="\"say \"hi\"\""];"#));
+}
@@ -0,0 +1,189 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Runs the built binary with the program on stdin, returning its exit code, stdout and stderr
+fn run(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_crafting_rust"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    (output.status.code().unwrap_or(-1),
+     String::from_utf8(output.stdout).unwrap(),
+     String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn deep_nesting_is_a_compile_error() {
+    for args in [vec!["test26.lex"], vec!["--vm", "test26.lex"], vec!["--ast", "test26.lex"]] {
+        let (code, stdout, stderr) = run(&args, "");
+        assert_eq!(code, 65, "{:?}: {}", args, stderr);
+        assert!(stdout.is_empty());
+        assert!(stderr.starts_with("Too much nesting at line: 2:"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn nesting_below_the_limit_still_runs() {
+    let source = format!("print {}1{};", "(1 + ".repeat(120), ")".repeat(120));
+    assert_eq!(run(&[], &source), (0, String::from("121\n"), String::new()));
+    assert_eq!(run(&["--vm"], &source), (0, String::from("121\n"), String::new()));
+}
+
+// The VM has no comparisons yet, so the loop is stopped by shifting nil through two variables
+#[test]
+fn vm_while_loops_jump_back() {
+    let source = "var i = 0; var more = true; var last = true;
+        while (more) { i = i + 1; print i; more = last; last = nil; }
+        print i * 10;";
+    assert_eq!(run(&["--vm"], source), (0, String::from("1\n2\n20\n"), String::new()));
+    assert_eq!(run(&[], source), (0, String::from("1\n2\n20\n"), String::new()));
+}
+
+#[test]
+fn vm_loads_constants_past_255() {
+    let terms: Vec<String> = (0..300).map(|n| n.to_string()).collect();
+    let source = format!("print {};", terms.join(" + "));
+    assert_eq!(run(&["--vm"], &source), (0, String::from("44850\n"), String::new()));
+    let (code, disassembly, _) = run(&["--disassemble", "test25.lex"], "");
+    assert_eq!(code, 0);
+    assert!(disassembly.contains("OpConstantLong 0299 '299'"), "{}", disassembly);
+}
+
+#[test]
+fn repl_continues_unfinished_statements_and_keeps_state() {
+    let input = "var x = 1;\nfun f() {\n  return x + 1;\n}\nprint f();\nx = 5;\nf();\n";
+    let (code, stdout, stderr) = run(&["--repl"], input);
+    assert_eq!((code, stderr.as_str()), (0, ""));
+    // One prompt per line read, `. ` while the function declaration is still open
+    assert_eq!(stdout, "> > . . > 2\n> 5\n> 6\n> ");
+}
+
+#[test]
+fn programs_are_read_from_piped_stdin() {
+    let source = "var greeting = \"hi\";\nprint greeting + \" there\";\nprint 1 + 2;\n";
+    let expected = (0, String::from("hi there\n3\n"), String::new());
+    assert_eq!(run(&[], source), expected);
+    assert_eq!(run(&["-"], source), expected);
+}
+
+#[test]
+fn exit_codes_tell_compile_and_runtime_errors_apart() {
+    let (code, stdout, stderr) = run(&[], "print 1;\nprint (1;");
+    assert_eq!((code, stdout.as_str()), (65, ""));
+    assert!(stderr.starts_with("Unexpected token"), "{}", stderr);
+
+    let (code, stdout, stderr) = run(&[], "print 1;\nprint missing;");
+    assert_eq!((code, stdout.as_str()), (70, "1\n"));
+    assert!(stderr.contains("missing"), "{}", stderr);
+
+    assert_eq!(run(&[], "var a = @;").0, 65);
+    assert_eq!(run(&["--vm"], "print -nil;").0, 70);
+    assert_eq!(run(&["no such file.lox"], "").0, 66);
+}
+
+#[test]
+fn input_reads_from_stdin() {
+    let program = std::env::temp_dir().join(format!("crafting_rust_input_{}.lox", std::process::id()));
+    std::fs::write(&program, "print input(\"a? \") + \"!\"; print input(\"\"); print input(\"\");").unwrap();
+    let result = run(&[program.to_str().unwrap()], "one\ntwo\n");
+    std::fs::remove_file(&program).unwrap();
+    assert_eq!(result, (0, String::from("a? one!\ntwo\nnil\n"), String::new()));
+}
+
+#[test]
+fn vm_computes_the_remainder() {
+    assert_eq!(run(&["--vm"], "print 7 % 3;\nprint -7 % 3;\nprint 7.5 % 2;"), (0, String::from("1\n-1\n1.5\n"), String::new()));
+}
+
+#[test]
+fn running_a_program_prints_only_its_output() {
+    let (code, stdout, stderr) = run(&[], "print 1 + 2;");
+    assert_eq!((code, stdout.as_str(), stderr.as_str()), (0, "3\n", ""));
+    assert!(!stdout.contains("OpConstant") && !stdout.contains("Chunk"));
+}
+
+#[test]
+fn tokens_flag_prints_one_line_per_token() {
+    let (code, stdout, stderr) = run(&["--tokens"], "var answer = 42;\nprint answer;");
+    assert_eq!((code, stderr.as_str()), (0, ""));
+    let lines: Vec<&str> = stdout.lines().collect();
+    // 5 + 3 tokens and EOF, nothing is run
+    assert_eq!(lines.len(), 9);
+    assert!(lines.iter().all(|l| l.starts_with("Token: ")));
+    assert_eq!(lines[3], "Token:  Type: NUMBER lexeme: 42 Literal: 42 line: 1:14");
+}
+
+#[test]
+fn ast_flag_prints_the_parsed_tree_from_stdin_or_a_file() {
+    let expected = (0, String::from("Print (1 + (2 * 3))\nExpr (((1 + 2)) * 3)\n"), String::new());
+    assert_eq!(run(&["--ast"], "print 1 + 2 * 3;\n(1 + 2) * 3;"), expected);
+    let (code, stdout, _) = run(&["--ast", "test23.lex"], "");
+    assert_eq!(code, 0);
+    assert!(!stdout.is_empty() && stdout.lines().all(|l| !l.starts_with("Token")));
+}
+
+#[test]
+fn write_stays_on_the_line_print_ends() {
+    let source = "write(\"a\"); write(\"b\"); print \"\"; write(1); write(\" \"); print 2;";
+    assert_eq!(run(&[], source), (0, String::from("ab\n1 2\n"), String::new()));
+}
+
+#[test]
+fn expression_statements_are_only_echoed_in_the_repl() {
+    let source = "fun f() { print \"called\"; }\nf();\n1 + 1;\n";
+    assert_eq!(run(&[], source), (0, String::from("called\n"), String::new()));
+    let (code, stdout, _) = run(&["--repl"], source);
+    assert_eq!((code, stdout.as_str()), (0, "> > called\nnil\n> 2\n> "));
+}
+
+#[test]
+fn trace_logs_statements_and_results_to_stderr() {
+    let (code, stdout, stderr) = run(&["--trace"], "var a = 1 + 2;\nif (a > 2) {\n    print a * 2;\n}\n");
+    assert_eq!((code, stdout.as_str()), (0, "6\n"));
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert!(lines[0].starts_with("VarDeclaration"), "{}", stderr);
+    assert!(lines.contains(&"  (1 + 2) => 3"), "{}", stderr);
+    assert!(lines.iter().any(|l| l.starts_with("If ((variable: a) > 2)")), "{}", stderr);
+    // The print inside the block is indented one level deeper than the if
+    assert!(lines.contains(&"  Print ((variable: a) * 2)"), "{}", stderr);
+    assert!(lines.contains(&"    ((variable: a) * 2) => 6"), "{}", stderr);
+    assert!(run(&[], "print 1;").2.is_empty());
+}
+
+#[test]
+fn vm_runs_the_branch_the_condition_picks() {
+    let source = "if (true) print \"then\"; else print \"else\";
+        if (nil) print \"then\"; else print \"else\";
+        if (0) { print \"zero\"; }
+        if (false) print \"skipped\";
+        if (true) if (false) print \"inner then\"; else print \"inner else\";
+        print \"after\";";
+    let expected = (0, String::from("then\nelse\nzero\ninner else\nafter\n"), String::new());
+    assert_eq!(run(&["--vm"], source), expected);
+    assert_eq!(run(&[], source), expected);
+}
+
+#[test]
+fn disassemble_prints_bytecode_instead_of_running() {
+    let program = std::env::temp_dir().join(format!("crafting_rust_disassemble_{}.lox", std::process::id()));
+    std::fs::write(&program, "print 1 + 2;").unwrap();
+    let (code, stdout, stderr) = run(&["--disassemble", program.to_str().unwrap()], "");
+    std::fs::remove_file(&program).unwrap();
+    assert_eq!((code, stderr.as_str()), (0, ""));
+    assert!(stdout.contains("OpConstant 0000 '1'") && stdout.contains("OpConstant 0001 '2'"), "{}", stdout);
+    assert!(stdout.contains("OpAdd") && stdout.contains("OpPrint"), "{}", stdout);
+    assert!(!stdout.lines().any(|l| l == "3"), "{}", stdout);
+}
+
+#[test]
+fn power_is_right_associative_in_both_back_ends() {
+    let source = "print 2 ** 10; print 2 ** 2 ** 3; print 2 ** -1; print 2 ** -2; print -2 ** 2; print 2 * 3 ** 2;";
+    let expected = (0, String::from("1024\n256\n0.5\n0.25\n-4\n18\n"), String::new());
+    assert_eq!(run(&[], source), expected);
+    assert_eq!(run(&["--vm"], source), expected);
+}
@@ -0,0 +1,94 @@
+use crafting_rust::vm::{Chunk, OpCode, SrcLocation, VmValue};
+
+#[test]
+fn instructions_on_one_line_collapse_whatever_their_columns() {
+    let mut chunk = Chunk::default();
+    chunk.write_constant(VmValue::Double(1.0), SrcLocation{line: 1, col: 7});
+    chunk.write_constant(VmValue::Double(2.0), SrcLocation{line: 1, col: 11});
+    chunk.write_chunk(OpCode::OpAdd as u8, SrcLocation{line: 1, col: 9});
+    chunk.write_chunk(OpCode::OpReturn as u8, SrcLocation{line: 2, col: 1});
+
+    let expected = "Chunk sum: \n\
+        \t0000 line:    1 col:   7 OpConstant 0000 '1'\n\
+        \t0002    | OpConstant 0001 '2'\n\
+        \t0004    | OpAdd\n\
+        \t0005 line:    2 col:   1 OpReturn\n";
+    assert_eq!(chunk.disassembly("sum"), expected);
+}
+
+#[test]
+fn same_line_ignores_the_column() {
+    let location = SrcLocation{line: 3, col: 5};
+    assert!(location.on_same_line(&SrcLocation{line: 3, col: 40}));
+    assert!(!location.on_same_line(&SrcLocation{line: 4, col: 5}));
+    assert_ne!(location, SrcLocation{line: 3, col: 40});
+}
+
+#[test]
+fn locations_are_stored_once_per_run() {
+    let mut chunk = Chunk::default();
+    for n in 0..50 {
+        chunk.write_constant(VmValue::Double(n as f64), SrcLocation{line: 1, col: 1});
+        chunk.write_chunk(OpCode::OpPop as u8, SrcLocation{line: 1, col: 1});
+    }
+    for _ in 0..50 {
+        chunk.write_chunk(OpCode::OpNil as u8, SrcLocation{line: 2, col: 1});
+    }
+    assert_eq!(chunk.count(), 200);
+    assert_eq!(chunk.location_runs(), 2);
+    assert_eq!(chunk.line_at(0), SrcLocation{line: 1, col: 1});
+    assert_eq!(chunk.line_at(149), SrcLocation{line: 1, col: 1});
+    assert_eq!(chunk.line_at(150), SrcLocation{line: 2, col: 1});
+    assert_eq!(chunk.line_at(199), SrcLocation{line: 2, col: 1});
+}
+
+#[test]
+fn constants_past_255_use_the_long_form() {
+    let mut chunk = Chunk::default();
+    for n in 0..300 {
+        chunk.write_constant(VmValue::Double(n as f64), SrcLocation{line: 1, col: 1});
+    }
+    assert_eq!(chunk.count(), 256 * 2 + 44 * 4);
+    let disassembly = chunk.disassembly("constants");
+    assert!(disassembly.contains("\t0510    | OpConstant 0255 '255'\n"), "{}", disassembly);
+    assert!(disassembly.contains("\t0512    | OpConstantLong 0256 '256'\n"), "{}", disassembly);
+    assert!(disassembly.contains("\t0684    | OpConstantLong 0299 '299'\n"), "{}", disassembly);
+}
+
+#[test]
+fn chunks_grow_past_any_initial_capacity() {
+    let mut chunk = Chunk::default();
+    for n in 0..1000 {
+        chunk.write_chunk((n % 256) as u8, SrcLocation{line: n / 100 + 1, col: 1});
+    }
+    assert_eq!(chunk.count(), 1000);
+    assert!(chunk.code().iter().enumerate().all(|(n, byte)| *byte == (n % 256) as u8));
+    assert_eq!(chunk.line_at(999), SrcLocation{line: 10, col: 1});
+    for n in 0..1000 {
+        assert_eq!(chunk.add_constant(VmValue::Double(n as f64)), n);
+    }
+}
+
+#[test]
+fn offsets_follow_each_operand_width() {
+    let mut chunk = Chunk::default();
+    let at = || SrcLocation{line: 1, col: 1};
+    chunk.write_constant(VmValue::Double(1.5), at());
+    chunk.write_chunk(OpCode::OpJump as u8, at());
+    for byte in [0, 4] {
+        chunk.write_chunk(byte, at());
+    }
+    let constant = chunk.add_constant(VmValue::Double(2.5));
+    chunk.write_chunk(OpCode::OpConstantLong as u8, at());
+    for byte in [(constant >> 16) as u8, (constant >> 8) as u8, constant as u8] {
+        chunk.write_chunk(byte, at());
+    }
+    chunk.write_chunk(OpCode::OpReturn as u8, at());
+
+    let expected = "Chunk widths: \n\
+        \t0000 line:    1 col:   1 OpConstant 0000 '1.5'\n\
+        \t0002    | OpJump 0002 -> 0009\n\
+        \t0005    | OpConstantLong 0001 '2.5'\n\
+        \t0009    | OpReturn\n";
+    assert_eq!(chunk.disassembly("widths"), expected);
+}
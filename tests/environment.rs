@@ -0,0 +1,37 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crafting_rust::environment::Environment;
+use crafting_rust::{run_source, Value};
+
+#[test]
+fn redeclaring_replaces_the_binding() {
+    assert_eq!(run_source("var x = 1; var x = 2; print x == 2;"), Ok(vec![String::from("true")]));
+    // Only globals, the resolver rejects a second declaration in a local scope
+    assert_eq!(run_source("{ var x = 1; fun f() { return x; } var x = 2; print f(); }").unwrap_err().len(), 1);
+}
+
+// Value's own clone can't be counted, but an array's reference count shows how many copies are alive
+#[test]
+fn define_keeps_the_value_it_is_given() {
+    let environment = Environment::new(None);
+    let elements = Rc::new(RefCell::new(vec![Value::Number(1.0)]));
+    environment.borrow_mut().define(String::from("x"), Some(Value::Array(Rc::clone(&elements))));
+    assert_eq!(Rc::strong_count(&elements), 2);
+
+    environment.borrow_mut().define(String::from("x"), Some(Value::Number(2.0)));
+    assert_eq!(Rc::strong_count(&elements), 1);
+    assert!(matches!(environment.borrow().get(String::from("x")), Ok(Value::Number(n)) if n == 2.0));
+}
+
+#[test]
+fn reading_before_initialization_reports_the_use() {
+    assert_eq!(run_source("var x;\nprint x;"),
+               Err(vec![String::from("Runtime error: UninitializedVariable 'x' at line: 2:7")]));
+    assert_eq!(run_source("fun f() {\n  var y;\n  return y;\n}\nf();"),
+               Err(vec![String::from("Runtime error: UninitializedVariable 'y' at line: 3:10")]));
+}
+
+#[test]
+fn assigning_initializes() {
+    assert_eq!(run_source("var x; x = 5; print x;"), Ok(vec![String::from("5")]));
+}
@@ -0,0 +1,32 @@
+use std::rc::Rc;
+use crafting_rust::environment::Environment;
+use crafting_rust::interpreter::{LoxClass, LoxFunction};
+use crafting_rust::Value;
+
+fn class(name: &str, superclass: Option<Rc<LoxClass>>, methods: &[&str]) -> Rc<LoxClass> {
+    let methods = methods.iter()
+        .map(|m| (m.to_string(), LoxFunction::new(m.to_string(), vec![], vec![], Environment::new(None))))
+        .collect();
+    Rc::new(LoxClass::new(String::from(name), superclass, methods))
+}
+
+// Copies of one function value compare equal, separately created methods don't
+fn declared_by(found: Option<LoxFunction>, class: &LoxClass, name: &str) -> bool {
+    let declared = class.methods().get(name).cloned().map(Value::LoxFunction);
+    found.map(Value::LoxFunction) == declared
+}
+
+#[test]
+fn nearest_override_wins() {
+    let base = class("Base", None, &["a", "b", "c"]);
+    let middle = class("Middle", Some(Rc::clone(&base)), &["b"]);
+    let leaf = class("Leaf", Some(Rc::clone(&middle)), &[]);
+
+    assert!(declared_by(leaf.find_method("a"), &base, "a"));
+    assert!(declared_by(leaf.find_method("b"), &middle, "b"));
+    assert!(!declared_by(leaf.find_method("b"), &base, "b"));
+    assert!(declared_by(leaf.find_method("c"), &base, "c"));
+    assert!(declared_by(middle.find_method("b"), &middle, "b"));
+    assert!(declared_by(base.find_method("b"), &base, "b"));
+    assert!(leaf.find_method("d").is_none());
+}
@@ -0,0 +1,64 @@
+use crafting_rust::{Parser, Scanner};
+
+// Every parse error of the program, rendered with its source snippet
+fn rendered_errors(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    let mut parser = Parser::default();
+    parser.set_source(source);
+    let errors = parser.parse(tokens).err().unwrap_or_default();
+    errors.iter().map(|e| parser.render_error(e)).collect()
+}
+
+#[test]
+fn caret_points_at_the_offending_token() {
+    let errors = rendered_errors("var a = 1;\nprint a +;\n");
+    assert_eq!(errors.len(), 1);
+    let lines: Vec<&str> = errors[0].lines().collect();
+    assert!(lines[0].ends_with("at line: 2:10."), "{}", errors[0]);
+    assert_eq!(lines[1..], ["  |", "2 | print a +;", "  |          ^"]);
+}
+
+#[test]
+fn caret_follows_char_columns_and_tabs() {
+    let errors = rendered_errors("\tprint \"é\" +;");
+    let lines: Vec<&str> = errors[0].lines().collect();
+    assert!(lines[0].ends_with("at line: 1:13."), "{}", errors[0]);
+    assert_eq!(lines[2], "1 | \tprint \"é\" +;");
+    assert_eq!(lines[3], "  | \t           ^");
+}
+
+#[test]
+fn lines_after_crlf_keep_their_numbers() {
+    let errors = rendered_errors("var a = 1;\r\n\r\nvar = 2;");
+    assert_eq!(errors[0].lines().skip(2).collect::<Vec<&str>>(), ["3 | var = 2;", "  |     ^"]);
+}
+
+#[test]
+fn every_recovered_error_gets_its_own_snippet() {
+    let errors = rendered_errors("print ;\nvar x = 1;\nprint );");
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].ends_with("1 | print ;\n  |       ^"), "{}", errors[0]);
+    assert!(errors[1].ends_with("3 | print );\n  |       ^"), "{}", errors[1]);
+}
+
+#[test]
+fn without_source_only_the_message_is_rendered() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("print ;"));
+    let (tokens, _) = scanner.scan_tokens();
+    let mut parser = Parser::default();
+    let errors = parser.parse(tokens).unwrap_err();
+    assert_eq!(parser.render_error(&errors[0]), errors[0].to_string());
+}
+
+#[test]
+fn jumps_outside_loops_report_one_error_each() {
+    for source in ["fun f() { break; }", "fun f() { break; print 1; } print 2;", "continue;", "if (true) { continue; }"] {
+        let errors = rendered_errors(source);
+        assert_eq!(errors.len(), 1, "{}: {:?}", source, errors);
+        assert!(errors[0].starts_with("Can't use '"), "{}", errors[0]);
+    }
+    assert_eq!(rendered_errors("break; while (true) { break; } continue;").len(), 2);
+}
@@ -0,0 +1,74 @@
+use crafting_rust::expr::Expr;
+use crafting_rust::stmt::Stmt;
+use crafting_rust::{Parser, Scanner};
+
+fn parse_expression(source: &str) -> Expr {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    match Parser::default().parse(tokens).unwrap().pop() {
+        Some(Stmt::Expression(expr)) => expr,
+        other => panic!("not an expression statement: {:?}", other),
+    }
+}
+
+// Parenthesizes every Binary and Unary node, so the nesting is visible in one string
+fn shape(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(left, operator, right) => format!("({} {} {})", shape(left), operator.lexeme, shape(right)),
+        Expr::Unary(operator, right) => format!("({}{})", operator.lexeme, shape(right)),
+        Expr::Grouping(_, inner) => format!("(group {})", shape(inner)),
+        Expr::Variable(name, _) => name.lexeme.clone(),
+        Expr::Literal(_, token) => token.lexeme.clone(),
+        other => panic!("unexpected expression: {:?}", other),
+    }
+}
+
+#[test]
+fn equality_chains_nest_to_the_left() {
+    let expr = parse_expression("1 == 2 == 3;");
+    let Expr::Binary(left, operator, right) = &expr else { panic!("{:?}", expr) };
+    assert_eq!(operator.lexeme, "==");
+    assert!(matches!(**left, Expr::Binary(..)));
+    assert!(matches!(**right, Expr::Literal(..)));
+    assert_eq!(shape(&expr), "((1 == 2) == 3)");
+    assert_eq!(shape(&parse_expression("1 != 2 == 3 != 4;")), "(((1 != 2) == 3) != 4)");
+}
+
+#[test]
+fn comparison_chains_nest_to_the_left() {
+    assert_eq!(shape(&parse_expression("1 < 2 <= 3 > 0;")), "(((1 < 2) <= 3) > 0)");
+    assert_eq!(shape(&parse_expression("1 < 2 == 3 >= 4;")), "((1 < 2) == (3 >= 4))");
+}
+
+#[test]
+fn lambdas_parse_as_callable_expressions() {
+    let expr = parse_expression("(fun (x) { return x * 2; })(21);");
+    let Expr::Call(callee, _, arguments) = &expr else { panic!("{:?}", expr) };
+    let Expr::Grouping(_, inner) = &**callee else { panic!("{:?}", callee) };
+    let Expr::Lambda(keyword, params, body) = &**inner else { panic!("{:?}", inner) };
+    assert_eq!(keyword.lexeme, "fun");
+    assert_eq!(params.iter().map(|p| p.lexeme.as_str()).collect::<Vec<&str>>(), ["x"]);
+    assert!(matches!(body[..], [Stmt::Return(..)]));
+    assert_eq!(arguments.len(), 1);
+}
+
+#[test]
+fn not_binds_tighter_than_equality() {
+    let expr = parse_expression("!a == b;");
+    let Expr::Binary(left, operator, _) = &expr else { panic!("{:?}", expr) };
+    assert_eq!(operator.lexeme, "==");
+    assert!(matches!(&**left, Expr::Unary(not, _) if not.lexeme == "!"));
+    assert_eq!(shape(&expr), "((!a) == b)");
+}
+
+#[test]
+fn not_of_a_grouping_negates_the_whole_comparison() {
+    let expr = parse_expression("!(a == b);");
+    let Expr::Unary(operator, right) = &expr else { panic!("{:?}", expr) };
+    assert_eq!(operator.lexeme, "!");
+    assert!(matches!(&**right, Expr::Grouping(_, inner) if matches!(**inner, Expr::Binary(..))));
+    assert_eq!(shape(&expr), "(!(group (a == b)))");
+    assert_eq!(shape(&parse_expression("!a < -b;")), "((!a) < (-b))");
+    assert_eq!(shape(&parse_expression("!!a;")), "(!(!a))");
+}
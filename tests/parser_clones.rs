@@ -0,0 +1,20 @@
+#![cfg(feature = "clone-stats")]
+
+use crafting_rust::token::Token;
+use crafting_rust::{Parser, Scanner};
+
+// Kept alone in its own test binary, as the clone counter is shared by the whole process
+#[test]
+fn parsing_clones_each_stored_token_at_most_once() {
+    let terms: Vec<String> = (0..500).map(|i| format!("(a{} * {} - -b.c[{}])", i, i, i)).collect();
+    let source = format!("print {};", terms.join(" + "));
+    let mut scanner = Scanner::default();
+    scanner.set_source(&source);
+    let (tokens, _) = scanner.scan_tokens();
+    let count = tokens.len();
+
+    let before = Token::clone_count();
+    Parser::default().parse(tokens).unwrap();
+    let clones = Token::clone_count() - before;
+    assert!(clones < count, "{} clones for {} tokens", clones, count);
+}
@@ -0,0 +1,28 @@
+use crafting_rust::{Parser, Resolver, Scanner};
+
+fn warnings(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+    let mut resolver = Resolver::default();
+    resolver.resolve(&statements);
+    assert!(resolver.errors().is_empty());
+    resolver.warnings().iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn unused_local_is_reported_once() {
+    let source = "fun f(unusedParam) {\n  var used = 1;\n  var unused = 2;\n  return used;\n}";
+    assert_eq!(warnings(source), vec!["Unused variable 'unused' at line: 3:7."]);
+}
+
+#[test]
+fn globals_loop_variables_and_assigned_only_locals() {
+    assert!(warnings("var never = 1; for (item in []) {}").is_empty());
+    // Assigning isn't reading
+    assert_eq!(warnings("{ var x; x = 1; }"), vec!["Unused variable 'x' at line: 1:7."]);
+    // A read from a closure counts, and a shadowed outer variable is tracked separately
+    assert_eq!(warnings("{ var a = 1; fun f() { return a; } { var a = 2; } f(); }"),
+               vec!["Unused variable 'a' at line: 1:42."]);
+}
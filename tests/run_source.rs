@@ -0,0 +1,321 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crafting_rust::interpreter::NativeFunction;
+use crafting_rust::{run_source, Interpreter, Parser, Resolver, RuntimeError, Scanner, SharedBuffer, Value};
+
+fn output(source: &str) -> Vec<String> {
+    run_source(source).unwrap()
+}
+
+#[test]
+fn returns_printed_lines() {
+    let source = "var a = 1; print a; write(\"b\"); write(2); print \"\"; print a + 2;";
+    assert_eq!(run_source(source), Ok(vec![String::from("1"), String::from("b2"), String::from("3")]));
+}
+
+#[test]
+fn interpreter_writes_to_the_given_buffer() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("for (var i = 0; i < 3; i = i + 1) print i * 2;"));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+    let locals = Resolver::default().resolve(&statements);
+
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.resolve(locals);
+    interpreter.interpret(statements).unwrap();
+    assert_eq!(output.lines(), vec!["0", "2", "4"]);
+}
+
+#[test]
+fn reports_scan_errors() {
+    assert_eq!(run_source("var a = @;"), Err(vec![String::from("Unexpected character: @ at line: 1:9.")]));
+}
+
+#[test]
+fn reports_every_parse_error() {
+    let errors = run_source("var = 1;\nprint ;").unwrap_err();
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn reports_resolve_errors() {
+    let errors = run_source("return 1;").unwrap_err();
+    assert_eq!(errors, vec![String::from("Can't return from top-level code. at line: 1:1.")]);
+}
+
+#[test]
+fn reports_runtime_errors() {
+    let errors = run_source("print missing;").unwrap_err();
+    assert_eq!(errors, vec![String::from("Runtime error: UndefinedVariable 'missing' at line: 1:7")]);
+}
+
+#[test]
+fn increments_need_a_variable() {
+    assert_eq!(run_source("var i = 0; i++; print i;"), Ok(vec![String::from("1")]));
+    assert_eq!(run_source("var a = [1];\na[0]++;"),
+               Err(vec![String::from("Operand of '++' must be a variable at line: 2:5.")]));
+    assert_eq!(run_source("--5;"), Err(vec![String::from("Operand of '--' must be a variable at line: 1:1.")]));
+}
+
+#[test]
+fn constants_need_an_initializer() {
+    assert_eq!(run_source("const a = 2; print a * a;"), Ok(vec![String::from("4")]));
+    let errors = run_source("const a;").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("Constant must be initialized at line: 1:8."), "{}", errors[0]);
+}
+
+#[test]
+fn closures_returned_from_methods_keep_this() {
+    let source = "class Counter {
+        incrementer() {
+            fun increment() { this.count = this.count + 1; return this.count; }
+            return increment;
+        }
+    }
+    var counter = Counter();
+    counter.count = 0;
+    var increment = counter.incrementer();
+    increment();
+    print increment();
+    print counter.count;";
+    assert_eq!(run_source(source), Ok(vec![String::from("2"), String::from("2")]));
+}
+
+#[test]
+fn input_reads_lines_from_the_given_reader() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("var name = input(\"name? \"); var rest = input(\"\"); print name; print rest; print input(\"\");"));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.set_input(Box::new(std::io::Cursor::new("Ada\r\nLovelace")));
+    interpreter.resolve(Resolver::default().resolve(&statements));
+    interpreter.interpret(statements).unwrap();
+    assert_eq!(output.lines(), vec!["name? Ada", "Lovelace", "nil"]);
+}
+
+#[test]
+fn clock_returns_non_decreasing_seconds() {
+    // Subtraction fails at runtime unless both values are numbers
+    let source = "var first = clock(); var second = clock(); print second - first >= 0; print first > 1700000000;";
+    assert_eq!(run_source(source), Ok(vec![String::from("true"), String::from("true")]));
+}
+
+#[test]
+fn step_limit_stops_infinite_loops() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("while (true) print \"again\";"));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.set_step_limit(10);
+    let error = interpreter.interpret(statements).unwrap_err();
+    assert!(matches!(error, RuntimeError::StepLimitExceeded{limit: 10, ..}), "{}", error);
+    // The while statement is the first of the 10 steps
+    assert_eq!(output.lines().len(), 9);
+}
+
+#[test]
+fn foreach_visits_every_element() {
+    assert_eq!(run_source("var sum = 0; for (n in [1, 2, 3, 4]) sum = sum + n; print sum;"), Ok(vec![String::from("10")]));
+    assert_eq!(run_source("for (n in []) print \"never\"; print \"done\";"), Ok(vec![String::from("done")]));
+    let errors = run_source("for (c in \"abc\") print c;").unwrap_err();
+    assert!(errors[0].starts_with("Runtime error: NotIterable"), "{:?}", errors);
+}
+
+#[test]
+fn len_counts_string_bytes() {
+    let source = "print len(\"hello\"); print len(\"\"); print len(\"été\"); print len([1, \"two\"]);";
+    assert_eq!(run_source(source), Ok(vec![String::from("5"), String::from("0"), String::from("5"), String::from("2")]));
+    let errors = run_source("print len(5);").unwrap_err();
+    assert!(errors[0].contains("len() expects a string, array or map, got '5'"), "{:?}", errors);
+}
+
+#[test]
+fn deeply_nested_blocks_resolve_each_scope() {
+    // Every level shadows x and reads it back once its inner block has ended; the body is
+    // called repeatedly so scopes are entered and left many times
+    const DEPTH: usize = 100;
+    let mut body = String::new();
+    for i in 0..DEPTH {
+        body.push_str(&format!("{{ var x = {}; var y{} = {};\n", i, i, i));
+    }
+    body.push_str(&format!("total = total + y0 + y{};\n", DEPTH - 1));
+    for _ in 0..DEPTH {
+        body.push_str("total = total + x; }\n");
+    }
+    let source = format!("var x = \"global\"; var total = 0;\nfun f() {{\n{}}}\nfor (var n = 0; n < 20; n = n + 1) f();\nprint total; print x;", body);
+    let per_call = (DEPTH - 1) + (0..DEPTH).sum::<usize>();
+    assert_eq!(run_source(&source), Ok(vec![(20 * per_call).to_string(), String::from("global")]));
+}
+
+#[test]
+fn failed_blocks_restore_the_enclosing_scope() {
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    for source in ["var a = \"outer\";", "{ var a = \"inner\"; { print a; nil + 1; } }", "print a;",
+                   "fun f() { { var a = \"local\"; return a; } } print f(); print a;"] {
+        let mut scanner = Scanner::default();
+        scanner.set_source(&String::from(source));
+        let (tokens, _) = scanner.scan_tokens();
+        let statements = Parser::default().parse(tokens).unwrap();
+        interpreter.resolve(Resolver::default().resolve(&statements));
+        let _ = interpreter.interpret(statements);
+    }
+    assert_eq!(output.lines(), vec!["inner", "outer", "local", "outer"]);
+}
+
+#[test]
+fn strings_compare_by_their_bytes() {
+    let source = "print \"a\" < \"b\"; print \"b\" <= \"a\"; print \"abc\" > \"abd\"; print \"b\" >= \"b\"; print \"B\" < \"a\";";
+    assert_eq!(output(source), vec!["true", "false", "false", "true", "true"]);
+    assert_eq!(output("print \"a\" == \"a\"; print \"a\" != \"b\"; print \"a\" == \"A\";"), vec!["true", "true", "false"]);
+}
+
+#[test]
+fn bools_and_nil_compare_for_equality() {
+    assert_eq!(output("print true == true; print true != false; print false == true;"), vec!["true", "true", "false"]);
+    assert_eq!(output("print nil == nil; print nil != nil;"), vec!["true", "false"]);
+}
+
+#[test]
+fn unary_minus_needs_a_number() {
+    assert_eq!(output("print -2; print - -2;"), vec!["-2", "2"]);
+    for operand in ["nil", "true", "\"s\""] {
+        assert_eq!(run_source(&format!("print -{};", operand)),
+                   Err(vec![String::from("Runtime error: OperandMustBeNumber at line: 1:7")]), "-{}", operand);
+    }
+}
+
+#[test]
+fn plus_with_a_string_concatenates() {
+    assert_eq!(output("print \"n=\" + 3; print 3 + \"!\"; print \"ok: \" + true; print 3 + 4;"), vec!["n=3", "3!", "ok: true", "7"]);
+}
+
+#[test]
+fn continue_still_runs_the_increment() {
+    let source = "var sum = 0; for (var i = 0; i < 10; i = i + 1) { if (i % 2 == 1) continue; sum = sum + i; } print sum;";
+    assert_eq!(output(source), vec!["20"]);
+}
+
+#[test]
+fn break_leaves_only_the_innermost_loop() {
+    assert_eq!(output("var n = 1; while (true) { if (n * n > 50) break; n = n + 1; } print n * n;"), vec!["64"]);
+    let source = "for (var j = 0; j < 2; j = j + 1) { for (var k = 0; true; k = k + 1) { if (k == 2) break; print j + k; } }";
+    assert_eq!(output(source), vec!["0", "1", "1", "2"]);
+}
+
+#[test]
+fn ternaries_nest_to_the_right() {
+    let source = "fun sign(n) { return n < 0 ? \"negative\" : n == 0 ? \"zero\" : \"positive\"; }
+    print sign(-3); print sign(0); print sign(5); print 1 > 2 ? 10 : 20;";
+    assert_eq!(output(source), vec!["negative", "zero", "positive", "20"]);
+}
+
+#[test]
+fn ternaries_skip_the_untaken_branch() {
+    let source = "var calls = 0; fun fail() { calls = calls + 1; return -\"not a number\"; }
+    print true ? \"taken\" : fail(); print false ? fail() : \"taken\"; print calls;";
+    assert_eq!(output(source), vec!["taken", "taken", "0"]);
+}
+
+#[test]
+fn lambdas_are_values_and_close_over_their_scope() {
+    let source = "var double = fun (a) { return a * 2; }; print double(4);
+    print (fun (x) { return x * 2; })(21);
+    fun apply(f, value) { return f(value); } print apply(fun (n) { return n + 1; }, 41);
+    fun adder(n) { return fun (x) { return x + n; }; } print adder(40)(2);
+    fun (x) { print x; }(\"statement\");";
+    assert_eq!(output(source), vec!["8", "42", "42", "42", "statement"]);
+}
+
+#[test]
+fn str_and_num_convert_between_strings_and_numbers() {
+    assert_eq!(output("print str(42) + \"!\"; print str(true); print len(str(1.5));"), vec!["42!", "true", "3"]);
+    assert_eq!(output("print num(\"3.14\"); print num(\"2\") + 1; print num(\"abc\") == nil;"), vec!["3.14", "3", "true"]);
+}
+
+#[test]
+fn calls_need_exactly_one_argument_per_parameter() {
+    let add = "fun add(a, b) {\n    return a + b;\n}\n";
+    assert_eq!(output(&format!("{}print add(1, 2);", add)), vec!["3"]);
+    assert_eq!(run_source(&format!("{}print add(1);", add)),
+               Err(vec![String::from("Runtime error: InvalidCall: Expected 2 arguments but got 1 at line: 4:12")]));
+    assert_eq!(run_source(&format!("{}print add(1, 2, 3);", add)),
+               Err(vec![String::from("Runtime error: InvalidCall: Expected 2 arguments but got 3 at line: 4:18")]));
+    assert_eq!(run_source("print len(\"a\", \"b\");"),
+               Err(vec![String::from("Runtime error: InvalidCall: Expected 1 arguments but got 2 at line: 1:19")]));
+}
+
+#[test]
+fn only_nil_and_false_are_falsey() {
+    let source = "if (0) print \"x\"; if (\"\") print \"y\"; if (nil) print \"no\"; else print \"z\";
+    fun f() {} if (f) print \"fun\"; if (false) print \"no\";";
+    assert_eq!(output(source), vec!["x", "y", "z", "fun"]);
+}
+
+#[test]
+fn assert_stops_at_the_failing_call() {
+    assert_eq!(output("print assert(true); assert(1); assert_eq(1 + 1, 2); assert_eq(\"a\" + \"b\", \"ab\");"), vec!["nil"]);
+    assert_eq!(run_source("print 1;\nassert(false);"),
+               Err(vec![String::from("Runtime error: AssertionFailed: 'false' is not truthy at line: 2:13")]));
+    assert_eq!(run_source("assert_eq(1, \"1\");"),
+               Err(vec![String::from("Runtime error: AssertionFailed: '1' is not equal to '1' at line: 1:17")]));
+}
+
+static SIDE_EFFECTS: AtomicUsize = AtomicUsize::new(0);
+
+fn side_effect(_interpreter: &mut Interpreter, _args: Vec<Value>) -> Result<Value, RuntimeError> {
+    SIDE_EFFECTS.fetch_add(1, Ordering::SeqCst);
+    Ok(Value::String(String::from("called")))
+}
+
+#[test]
+fn and_or_skip_the_right_operand_once_the_result_is_known() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("print nil or \"default\"; print false and sideEffect(); print \"left\" or sideEffect();
+        print 1 or 2 or 3 and nil or \"last\"; print nil or false or \"last\";"));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::default();
+    interpreter.set_output(Box::new(output.clone()));
+    interpreter.define_native(NativeFunction{name: String::from("sideEffect"), arity: 0, callable: side_effect});
+    interpreter.interpret(statements).unwrap();
+    assert_eq!(output.lines(), vec!["default", "false", "left", "1", "last"]);
+    assert_eq!(SIDE_EFFECTS.load(Ordering::SeqCst), 0);
+
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("print true and sideEffect(); print nil or sideEffect();"));
+    let (tokens, _) = scanner.scan_tokens();
+    interpreter.interpret(Parser::default().parse(tokens).unwrap()).unwrap();
+    assert_eq!(output.lines()[5..], ["called", "called"]);
+    assert_eq!(SIDE_EFFECTS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn undefined_variables_are_reported_where_they_are_used() {
+    for (source, position) in [("var a = 1;\nprint (a + (  missing));", "2:15"), ("var a = 1;\na = (missing);", "2:6"),
+                               ("missing = 1;", "1:1"), ("print \"x\" + missing;", "1:13")] {
+        assert_eq!(run_source(source), Err(vec![format!("Runtime error: UndefinedVariable 'missing' at line: {}", position)]));
+    }
+}
+
+#[test]
+fn only_functions_and_classes_are_callable() {
+    for (source, position) in [("\"not a function\"();", "1:18"), ("print 1;\n  5();", "2:5"), ("var t = true; t();", "1:17")] {
+        assert_eq!(run_source(source),
+                   Err(vec![format!("Runtime error: InvalidCall: Can only call functions and classes at line: {}", position)]));
+    }
+}
@@ -0,0 +1,224 @@
+use std::rc::Rc;
+use crafting_rust::Scanner;
+use crafting_rust::token::{Literal, TokenType};
+
+// (lexeme, line, col) of every token except the final EOF
+fn positions(source: &str) -> Vec<(String, usize, usize)> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert!(errors.is_empty());
+    tokens[..tokens.len() - 1].iter().map(|t| (t.lexeme.clone(), t.line, t.col)).collect()
+}
+
+fn errors(source: &str) -> Vec<String> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (_, errors) = scanner.scan_tokens();
+    errors.iter().map(|e| e.to_string()).collect()
+}
+
+// Values of the number literals, in source order
+fn numbers(source: &str) -> Vec<f64> {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert!(errors.is_empty(), "{:?}", errors.iter().map(|e| e.to_string()).collect::<Vec<String>>());
+    tokens.iter().filter_map(|t| match t.literal {
+        Literal::Number(n) => Some(n),
+        _ => None,
+    }).collect()
+}
+
+fn expected(tokens: &[(&str, usize, usize)]) -> Vec<(String, usize, usize)> {
+    tokens.iter().map(|(lexeme, line, col)| (lexeme.to_string(), *line, *col)).collect()
+}
+
+#[test]
+fn line_breaks_agree_on_positions() {
+    let want = expected(&[("var", 1, 1), ("a", 1, 5), (";", 1, 6), ("print", 2, 1), ("a", 2, 7), (";", 2, 8)]);
+    assert_eq!(positions("var a;\nprint a;"), want);
+    assert_eq!(positions("var a;\r\nprint a;"), want);
+    assert_eq!(positions("var a;\rprint a;"), want);
+}
+
+#[test]
+fn crlf_counts_once_after_comments_and_strings() {
+    let source = "// comment\r\n/* block\r\ncomment */ \"two\r\nlines\"\r\n  x\r\ry";
+    let want = expected(&[("\"two\r\nlines\"", 3, 12), ("x", 5, 3), ("y", 7, 1)]);
+    assert_eq!(positions(source), want);
+}
+
+#[test]
+fn unterminated_string_points_at_its_opening_quote() {
+    assert_eq!(errors("var s = \"never\nclosed\r\nat all;"), vec!["Unterminated string at line: 1:9."]);
+}
+
+#[test]
+fn numbers_overflowing_to_infinity_are_rejected() {
+    assert_eq!(errors("print 1e400;"), vec!["The number literal is too large at line: 1:7."]);
+    assert_eq!(errors(&format!("print {};", "9".repeat(400))), vec!["The number literal is too large at line: 1:7."]);
+    assert!(errors("print 1e308; print 1e-400;").is_empty());
+}
+
+#[test]
+fn equal_string_literals_share_storage() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&"print \"same\"; print \"other\";\n".repeat(10));
+    let (tokens, _) = scanner.scan_tokens();
+    let strings: Vec<Rc<str>> = tokens.iter().filter_map(|t| match &t.literal {
+        Literal::String(s) if &**s == "same" => Some(Rc::clone(s)),
+        _ => None,
+    }).collect();
+    assert_eq!(strings.len(), 10);
+    assert!(strings.iter().all(|s| Rc::ptr_eq(s, &strings[0])));
+}
+
+fn string_value(source: &str) -> String {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert!(errors.is_empty(), "{:?}", errors);
+    match &tokens[0].literal {
+        Literal::String(s) => s.to_string(),
+        other => panic!("not a string: {}", other),
+    }
+}
+
+#[test]
+fn escape_sequences_are_decoded() {
+    assert_eq!(string_value(r#""a\nb""#), "a\nb");
+    assert_eq!(string_value(r#""a\tb""#), "a\tb");
+    assert_eq!(string_value(r#""a\rb""#), "a\rb");
+    assert_eq!(string_value(r#""a\\b""#), "a\\b");
+    assert_eq!(string_value(r#""say \"hi\"""#), "say \"hi\"");
+    assert_eq!(string_value(r#""a\0b""#), "a\0b");
+    assert_eq!(string_value(r#""\u{48}\u{1F600}""#), "H\u{1F600}");
+}
+
+#[test]
+fn invalid_escapes_are_reported_at_their_backslash() {
+    assert_eq!(errors("print \"ab\\qc\";"), vec!["Invalid escape sequence '\\q' in string at line: 1:10."]);
+    assert_eq!(errors("print \"one\ntw\\u{110000}o\";"), vec!["Invalid unicode escape '\\u{110000}' in string at line: 2:3."]);
+}
+
+#[test]
+fn backslash_before_a_line_break_still_counts_the_line() {
+    for source in ["var s = \"a\\\nb\";\nprint c;", "var s = \"a\\\r\nb\";\r\nprint c;"] {
+        let mut scanner = Scanner::default();
+        scanner.set_source(&String::from(source));
+        let (tokens, errors) = scanner.scan_tokens();
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, vec!["Invalid escape sequence '\\' at end of line in string at line: 1:11."]);
+        let c = tokens.iter().find(|t| t.lexeme == "c").unwrap();
+        assert_eq!((c.line, c.col), (3, 7));
+    }
+}
+
+#[test]
+fn block_comments_are_skipped() {
+    assert_eq!(positions("a /* one */ b"), expected(&[("a", 1, 1), ("b", 1, 13)]));
+    assert_eq!(positions("a /* one * / ** /\ntwo\r\nthree */ b /**/c"), expected(&[("a", 1, 1), ("b", 3, 10), ("c", 3, 16)]));
+}
+
+#[test]
+fn unterminated_block_comment_points_at_its_opening() {
+    assert_eq!(errors("var a;\n  /* never\nclosed *"), vec!["Unterminated block comment at line: 2:3."]);
+    assert_eq!(errors("/*"), vec!["Unterminated block comment at line: 1:1."]);
+}
+
+#[test]
+fn columns_are_where_tokens_start_on_later_lines() {
+    let source = "var first = 1;\n  print first + 22;\n\t\"multi\nline\" + third;";
+    let want = expected(&[
+        ("var", 1, 1), ("first", 1, 5), ("=", 1, 11), ("1", 1, 13), (";", 1, 14),
+        ("print", 2, 3), ("first", 2, 9), ("+", 2, 15), ("22", 2, 17), (";", 2, 19),
+        ("\"multi\nline\"", 3, 2), ("+", 4, 7), ("third", 4, 9), (";", 4, 14),
+    ]);
+    assert_eq!(positions(source), want);
+}
+
+#[test]
+fn unexpected_character_is_one_error_with_its_position() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("print 1;\nvar @ = 2;"));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].message.as_str(), errors[0].line, errors[0].col), ("Unexpected character: @", 2, 5));
+    assert!(scanner.had_error());
+    // Scanning goes on past the error
+    assert_eq!(tokens.iter().filter(|t| t.lexeme == "2").count(), 1);
+}
+
+#[test]
+fn exponents_scale_numbers() {
+    assert_eq!(numbers("1e3 1.5e-2 2E10 6.02e+23"), vec![1000.0, 0.015, 2e10, 6.02e23]);
+    assert_eq!(errors("print 3e;"), vec!["Expected digits in number exponent at line: 1:7."]);
+    assert_eq!(errors("print 4e+;"), vec!["Expected digits in number exponent at line: 1:7."]);
+}
+
+#[test]
+fn underscores_only_separate_digits() {
+    assert_eq!(numbers("1_000_000 1_000.5_5 2_5e1_0"), vec![1_000_000.0, 1000.55, 2.5e11]);
+    // A leading underscore starts an identifier instead
+    assert_eq!(positions("_1"), expected(&[("_1", 1, 1)]));
+    for literal in ["1_", "1__2", "1._5", "1_.5"] {
+        assert_eq!(errors(&format!("print {};", literal)), vec!["Underscore in number must be between digits at line: 1:7."], "{}", literal);
+    }
+}
+
+#[test]
+fn hexadecimal_and_binary_literals() {
+    assert_eq!(numbers("0xFF 0x1f 0X1F 0xdead_BEEF 0b1111 0B1010"), vec![255.0, 31.0, 31.0, 3735928559.0, 15.0, 10.0]);
+    assert_eq!(errors("print 0x;"), vec!["Expected digits in hexadecimal literal at line: 1:7."]);
+    assert_eq!(errors("print 0b;"), vec!["Expected digits in binary literal at line: 1:7."]);
+    assert_eq!(errors("print 0b12;"), vec!["Invalid digit '2' in binary literal at line: 1:7."]);
+    assert_eq!(errors("print 0xFG;"), vec!["Invalid digit 'G' in hexadecimal literal at line: 1:7."]);
+}
+
+#[test]
+fn identifiers_starting_with_o_are_not_or() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("orchid o or one"));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert!(errors.is_empty());
+    let kinds: Vec<(TokenType, &str)> = tokens.iter().map(|t| (t.token_type, t.lexeme.as_str())).collect();
+    assert_eq!(kinds, [(TokenType::Identifier, "orchid"), (TokenType::Identifier, "o"), (TokenType::Or, "or"),
+                       (TokenType::Identifier, "one"), (TokenType::EOF, "")]);
+}
+
+#[test]
+fn line_comment_can_end_the_source() {
+    assert_eq!(positions("print 1;\n// trailing"), expected(&[("print", 1, 1), ("1", 1, 7), (";", 1, 8)]));
+    assert!(positions("//").is_empty());
+}
+
+#[test]
+fn unicode_escapes_need_a_scalar_value_in_braces() {
+    assert_eq!(string_value(r#""\u{41}""#), "A");
+    assert_eq!(string_value(r#""caf\u{e9}""#), "café");
+    assert_eq!(errors(r#"print "\u{D800}";"#), vec!["Invalid unicode escape '\\u{D800}' in string at line: 1:8."]);
+    assert_eq!(errors(r#"print "\u41";"#), vec!["Expected '{' after '\\u' in string at line: 1:8."]);
+    assert_eq!(errors(r#"print "\u{}";"#), vec!["Empty unicode escape in string at line: 1:8."]);
+    assert_eq!(errors(r#"print "\u{41";"#), vec!["Expected '}' after unicode escape in string at line: 1:8."]);
+}
+
+#[test]
+fn non_ascii_source_counts_columns_in_chars() {
+    let source = "var café = \"été\"; /* déjà vu */ print café;";
+    let want = expected(&[("var", 1, 1), ("café", 1, 5), ("=", 1, 10), ("\"été\"", 1, 12), (";", 1, 17),
+                          ("print", 1, 33), ("café", 1, 39), (";", 1, 43)]);
+    assert_eq!(positions(source), want);
+    assert_eq!(string_value("\"naïve ü\" // commentaire"), "naïve ü");
+    assert_eq!(errors("print \"ü\" + § ;"), vec!["Unexpected character: § at line: 1:13."]);
+}
+
+#[test]
+fn number_tokens_keep_their_source_lexeme() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("print 1.50;"));
+    let (tokens, _) = scanner.scan_tokens();
+    assert_eq!(tokens[1].token_type, TokenType::Number);
+    assert_eq!(tokens[1].lexeme, "1.50");
+    assert!(matches!(tokens[1].literal, Literal::Number(n) if n == 1.5));
+}
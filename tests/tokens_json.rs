@@ -0,0 +1,97 @@
+use crafting_rust::Scanner;
+use crafting_rust::token::{to_json_array, Literal, Token, TokenType};
+
+fn tokens_json(source: &str) -> String {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, errors) = scanner.scan_tokens();
+    assert!(errors.is_empty());
+    to_json_array(&tokens)
+}
+
+// Minimal recursive descent over the JSON grammar, returns the rest of the input after one value
+fn value(s: &str) -> Option<&str> {
+    let s = s.trim_start();
+    let c = s.chars().next()?;
+    match c {
+        '[' => sequence(&s[1..], ']', value),
+        '{' => sequence(&s[1..], '}', |s| {
+            let s = string(s.trim_start())?.trim_start();
+            value(s.strip_prefix(':')?)
+        }),
+        '"' => string(s),
+        't' => s.strip_prefix("true"),
+        'f' => s.strip_prefix("false"),
+        'n' => s.strip_prefix("null"),
+        _ => {
+            let end = s.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(s.len());
+            s[..end].parse::<f64>().ok().map(|_| &s[end..])
+        }
+    }
+}
+
+fn sequence(s: &str, close: char, item: fn(&str) -> Option<&str>) -> Option<&str> {
+    if let Some(rest) = s.trim_start().strip_prefix(close) {
+        return Some(rest);
+    }
+    let mut s = s;
+    loop {
+        s = item(s)?.trim_start();
+        match s.chars().next()? {
+            ',' => s = &s[1..],
+            c if c == close => return Some(&s[1..]),
+            _ => return None,
+        }
+    }
+}
+
+fn string(s: &str) -> Option<&str> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some(&s[i + 2..]),
+            '\\' => match chars.next()?.1 {
+                'u' => for _ in 0..4 { chars.next()?.1.to_digit(16)?; },
+                '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' => {}
+                _ => return None,
+            },
+            c if (c as u32) < 0x20 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_valid_json(s: &str) -> bool {
+    value(s).is_some_and(|rest| rest.trim().is_empty())
+}
+
+#[test]
+fn strings_and_numbers_serialize_as_valid_json() {
+    let json = tokens_json("print \"a\\\"b\\tc\" + 1.5;");
+    assert!(is_valid_json(&json), "{}", json);
+    assert_eq!(json, concat!(
+        r#"[{"type":"PRINT","lexeme":"print","literal":null,"line":1,"col":1},"#,
+        r#"{"type":"STRING","lexeme":"\"a\\\"b\\tc\"","literal":"a\"b\tc","line":1,"col":7},"#,
+        r#"{"type":"PLUS","lexeme":"+","literal":null,"line":1,"col":17},"#,
+        r#"{"type":"NUMBER","lexeme":"1.5","literal":1.5,"line":1,"col":19},"#,
+        r#"{"type":"SEMICOLON","lexeme":";","literal":null,"line":1,"col":22},"#,
+        r#"{"type":"EOF","lexeme":"","literal":null,"line":1,"col":23}]"#));
+}
+
+#[test]
+fn multiline_strings_keep_newlines_escaped() {
+    let json = tokens_json("var s = \"one\ntwo\";");
+    assert!(is_valid_json(&json), "{}", json);
+    assert!(json.contains(r#""literal":"one\ntwo""#), "{}", json);
+    assert!(json.contains(r#""type":"SEMICOLON","lexeme":";","literal":null,"line":2,"col":5"#), "{}", json);
+}
+
+#[test]
+fn infinite_numbers_become_null() {
+    for n in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+        let json = to_json_array(&[Token::new(TokenType::Number, String::from("1e400"), Literal::Number(n), 1, 1)]);
+        assert!(is_valid_json(&json), "{}", json);
+        assert_eq!(json, r#"[{"type":"NUMBER","lexeme":"1e400","literal":null,"line":1,"col":1}]"#);
+    }
+}
@@ -0,0 +1,107 @@
+use crafting_rust::environment::Environment;
+use crafting_rust::interpreter::LoxFunction;
+use crafting_rust::{run_source, Value};
+use crafting_rust::vm::format_number;
+
+fn output(source: &str) -> Vec<String> {
+    run_source(source).unwrap()
+}
+
+#[test]
+fn scalars_are_copied() {
+    assert_eq!(output("var x = 1; var y = x; y = 2; print x; print y;"), vec!["1", "2"]);
+    assert_eq!(output("var s = \"a\"; var t = s; t = t + \"b\"; print s; print t;"), vec!["a", "ab"]);
+}
+
+#[test]
+fn arrays_are_shared() {
+    let source = "var a = [1, 2]; var b = a; b[0] = 10; print a[0]; print a == b; print a == [10, 2];";
+    assert_eq!(output(source), vec!["10", "true", "false"]);
+}
+
+#[test]
+fn maps_and_instances_are_shared() {
+    assert_eq!(output("var m = {\"k\": 1}; var n = m; n[\"k\"] = 2; print m[\"k\"];"), vec!["2"]);
+    assert_eq!(output("class P {} var p = P(); var q = p; q.x = 3; print p.x;"), vec!["3"]);
+}
+
+#[test]
+fn arguments_share_like_assignments() {
+    let source = "fun push(xs, n) { xs[0] = n; n = 0; } var a = [1]; var n = 5; push(a, n); print a[0]; print n;";
+    assert_eq!(output(source), vec!["5", "5"]);
+}
+
+#[test]
+fn functions_print_their_signature() {
+    assert_eq!(output("fun add(a, b) { return a + b; } print add;"), vec!["<fn add(a, b)>"]);
+    assert_eq!(output("fun none() {} print none;"), vec!["<fn none()>"]);
+    assert_eq!(output("print fun (x) { return x; };"), vec!["<fn anonymous(x)>"]);
+    assert_eq!(output("print clock; print \"f: \" + len;"), vec!["<native fn clock>", "f: <native fn len>"]);
+}
+
+#[test]
+fn methods_print_like_functions() {
+    assert_eq!(output("class P { area(w, h) { return w * h; } } print P().area;"), vec!["<fn area(w, h)>"]);
+}
+
+#[test]
+fn arrays_and_maps_containing_themselves_print_once() {
+    assert_eq!(output("var a = [1]; a[0] = a; print a;"), vec!["[[...]]"]);
+    assert_eq!(output("var a = [1, 2]; var b = [a]; a[1] = b; print a;"), vec!["[1, [[...]]]"]);
+    assert_eq!(output("var m = {\"k\": 1}; m[\"self\"] = m; print m;"), vec!["{k: 1, self: {...}}"]);
+    assert_eq!(output("var m = {}; var a = [m]; m[\"a\"] = a; print a; print m;"), vec!["[{a: [...]}]", "{a: [{...}]}"]);
+}
+
+#[test]
+fn shared_but_acyclic_values_print_in_full() {
+    assert_eq!(output("var a = [1]; print [a, a];"), vec!["[[1], [1]]"]);
+}
+
+#[test]
+fn equality_holds_only_for_the_same_value() {
+    // Two values of every variant; a value equals itself and nothing else, other variants included
+    let table = [
+        ("nil", "nil"), ("bool", "true"), ("bool", "false"), ("number", "1"), ("number", "2"),
+        ("string", "\"1\""), ("string", "\"true\""), ("function", "f"), ("function", "g"),
+        ("instance", "a"), ("instance", "b"),
+    ];
+    for (left_kind, left) in table {
+        for (right_kind, right) in table {
+            let source = format!("class A {{}} var a = A(); var b = A(); fun f() {{}} fun g() {{}}\n\
+                                  print {} == {}; print {} != {};", left, right, left, right);
+            let equal = left == right;
+            assert_eq!(output(&source), vec![equal.to_string(), (!equal).to_string()],
+                       "{} {} == {} {}", left_kind, left, right_kind, right);
+        }
+    }
+}
+
+#[test]
+fn nil_and_whole_numbers_print_like_their_source() {
+    assert_eq!(output("print nil; print 1 + 1; print 10 / 2; print 5 / 2;"), vec!["nil", "2", "5", "2.5"]);
+}
+
+#[test]
+fn numbers_print_whole_or_shortest_round_trip() {
+    let source = "print 3; print 3.5; print 0.1 + 0.2; print 9007199254740992; print 1000000000000000000000; print -1 / 2;";
+    let expected = vec!["3", "3.5", "0.30000000000000004", "9007199254740992", "1e21", "-0.5"];
+    assert_eq!(output(source), expected);
+    // The VM prints through the same helper
+    let printed: Vec<String> = [3.0, 3.5, 0.1 + 0.2, 9007199254740992.0, 1e21, -0.5].iter().map(|n| format_number(*n)).collect();
+    assert_eq!(printed, expected);
+}
+
+#[test]
+fn values_compare_by_contents_and_functions_by_identity() {
+    assert_eq!(Value::Number(1.0), Value::Number(1.0));
+    assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+    assert_eq!(Value::String(String::from("a")), Value::String(String::from("a")));
+    assert_ne!(Value::Number(1.0), Value::Bool(true));
+    // Two closures made from the same source are distinct, a copy of one is the same function
+    let closure = || Value::LoxFunction(LoxFunction::new(String::from("f"), vec![], vec![], Environment::new(None)));
+    let f = closure();
+    assert_eq!(f.clone(), f);
+    assert_ne!(closure(), closure());
+    assert_eq!(output("fun make() { fun counter() {} return counter; } var f = make(); print f == f; print make() == make();"),
+               vec!["true", "false"]);
+}
@@ -0,0 +1,71 @@
+use crafting_rust::compiler::Compiler;
+use crafting_rust::vm::{Chunk, InterpretResult, OpCode, SrcLocation, Vm, VmValue};
+use crafting_rust::{Parser, Scanner};
+
+fn run(source: &str) -> Vm {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+    let mut vm = Vm::default();
+    for chunk in Compiler::default().compile(&statements).unwrap() {
+        assert!(matches!(vm.interpret(chunk), InterpretResult::InterpretOk));
+    }
+    vm
+}
+
+#[test]
+fn expression_statements_leave_the_stack_empty() {
+    assert_eq!(run("1 + 2; (3 - 4) * 5;").stack_len(), 0);
+    assert_eq!(run("var x = 1; x = x + 1; -x;").stack_len(), 0);
+}
+
+#[test]
+fn pushing_past_the_stack_is_a_runtime_error() {
+    let mut chunk = Chunk::default();
+    for _ in 0..300 {
+        chunk.write_constant(VmValue::Double(1.0), SrcLocation{line: 1, col: 1});
+    }
+    chunk.write_chunk(OpCode::OpReturn as u8, SrcLocation{line: 1, col: 1});
+    assert!(matches!(Vm::default().interpret(chunk), InterpretResult::InterpretRuntimeError));
+}
+
+fn compile_error(source: &str) -> String {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from(source));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+    match Compiler::default().compile(&statements) {
+        Ok(_) => panic!("compiled: {}", source),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn variables_declared_in_blocks_are_rejected() {
+    assert_eq!(compile_error("{ var a = 1; }"), "Local variables are not supported by the VM yet at line: 1:7.");
+    assert_eq!(compile_error("if (true) { { var a; } }"), "Local variables are not supported by the VM yet at line: 1:19.");
+    assert_eq!(run("var a = 1; { a = a + 1; } while (a) { a = nil; } var b = 2;").stack_len(), 0);
+}
+
+#[test]
+fn globals_keep_their_value_across_statements() {
+    let vm = run("var x = 1; print x; x = 2; print x;");
+    assert_eq!(vm.global("x").map(|v| v.to_string()), Some(String::from("2")));
+    assert_eq!(vm.stack_len(), 0);
+    // Declared without a value reads as nil, the tree-walker reports UninitializedVariable instead
+    assert_eq!(run("var y; var z = y;").global("z").map(|v| v.to_string()), Some(String::from("nil")));
+}
+
+#[test]
+fn reading_an_undefined_global_is_a_runtime_error() {
+    let mut scanner = Scanner::default();
+    scanner.set_source(&String::from("var x = 1; print missing; x = 2;"));
+    let (tokens, _) = scanner.scan_tokens();
+    let statements = Parser::default().parse(tokens).unwrap();
+    let mut vm = Vm::default();
+    let results: Vec<InterpretResult> = Compiler::default().compile(&statements).unwrap().into_iter().map(|c| vm.interpret(c)).collect();
+    assert!(matches!(results[..], [InterpretResult::InterpretOk, InterpretResult::InterpretRuntimeError, InterpretResult::InterpretOk]));
+    assert!(vm.global("missing").is_none());
+    assert_eq!(vm.stack_len(), 0);
+}